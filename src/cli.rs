@@ -0,0 +1,981 @@
+use crate::io::{eputs, itoa, puts, Cursor, InputBuffer, Read, Stdout};
+use crate::random::{entropy_seed, Rng};
+use crate::regex::{
+    debug_print_side_by_side, equivalent, equivalent_anchored, equivalent_dfas, Backtracker,
+    BudgetExceeded, Construction, Derivatives, Dfa, Difference, Flags, Glushkov, LazyDfa, Nfa,
+    ReverseSearch, Simplified, Word, MAX_WORD_LEN,
+};
+use crate::scanner::{Action, Scanner, INITIAL, UNMATCHED};
+use crate::sys::{close, exit, open, Args};
+
+// Subcommands, for when `compilers` is run with arguments:
+//
+//     compilers equivalent <regex> <regex>
+//     compilers combine <and|or|minus> <regex> <regex>
+//     compilers combine not <regex>
+//     compilers enumerate <regex> [max-len [count]]
+//     compilers simplify <regex>
+//     compilers generate <regex> [count [seed]]
+//     compilers near-miss <regex> [count [seed]]
+//     compilers grep [-cinov] <regex> [file...]
+//     compilers find <regex> <input>
+//     compilers glob <glob> [path...]
+//     compilers stream <regex> [file]
+//     compilers scan <input>
+//     compilers trace <regex> <input>
+//     compilers backtrack <regex> <input> [budget]
+//     compilers lazy <regex> <input...>
+//     compilers derive <regex> <input>
+//     compilers sizes [regex...]
+//     compilers dot <regex>
+//     compilers table <regex>
+//     compilers json [-d] <regex>
+//     compilers load [-d]
+//
+// Exit status is 0 for "yes" (or "found some"), 1 for "no" and 2 for a usage error or any
+// other error, such as a bad pattern or a file that can't be read.
+
+const USAGE: &str = "usage: compilers equivalent <regex> <regex>
+       compilers combine <and|or|minus> <regex> <regex>
+       compilers combine not <regex>
+       compilers enumerate <regex> [max-len [count]]
+       compilers simplify <regex>
+       compilers generate <regex> [count [seed]]
+       compilers near-miss <regex> [count [seed]]
+       compilers grep [-cinov] <regex> [file...]
+       compilers find <regex> <input>
+       compilers glob <glob> [path...]
+       compilers stream <regex> [file]
+       compilers scan <input>
+       compilers trace <regex> <input>
+       compilers backtrack <regex> <input> [budget]
+       compilers lazy <regex> <input...>
+       compilers derive <regex> <input>
+       compilers sizes [regex...]
+       compilers dot <regex>
+       compilers table <regex>
+       compilers json [-d] <regex>
+       compilers load [-d]
+";
+
+// The longest string `generate` and `near-miss` print.
+const GENERATE_MAX_LEN: usize = 16;
+
+// How many DFA states `lazy` caches: few, so that the cache is seen to fill up.
+const LAZY_CACHE_STATES: usize = 16;
+
+// How many steps `backtrack` takes before it gives up, unless told otherwise.
+const BACKTRACK_BUDGET: usize = 1_000_000;
+
+pub(crate) fn run(args: Args) -> ! {
+    match args.get(1) {
+        Some(b"equivalent") => equivalent_command(args),
+        Some(b"combine") => combine_command(args),
+        Some(b"enumerate") => enumerate_command(args),
+        Some(b"simplify") => simplify_command(args),
+        Some(b"generate") => generate_command(args, false),
+        Some(b"near-miss") => generate_command(args, true),
+        Some(b"grep") => grep_command(args),
+        Some(b"find") => find_command(args),
+        Some(b"glob") => glob_command(args),
+        Some(b"stream") => stream_command(args),
+        Some(b"scan") => scan_command(args),
+        Some(b"trace") => trace_command(args),
+        Some(b"backtrack") => backtrack_command(args),
+        Some(b"lazy") => lazy_command(args),
+        Some(b"derive") => derive_command(args),
+        Some(b"sizes") => sizes_command(args),
+        Some(b"dot") => dot_command(args),
+        Some(b"table") => table_command(args),
+        Some(b"json") => json_command(args),
+        Some(b"load") => load_command(args),
+        _ => usage(),
+    }
+}
+
+fn usage() -> ! {
+    eputs(USAGE);
+    exit(2);
+}
+
+// Print whether two patterns match the same strings; if not, a string that shows it.
+fn equivalent_command(args: Args) -> ! {
+    let (Some(a), Some(b), None) = (args.get(2), args.get(3), args.get(4)) else {
+        usage();
+    };
+
+    // Patterns with "&" or "~" have no NFA, so then both go through derivatives.
+    let difference = match (Construction::for_pattern(a), Construction::for_pattern(b)) {
+        (Construction::Thompson, Construction::Thompson) => equivalent(
+            &Nfa::<256>::from_regex_bytes(a),
+            &Nfa::<256>::from_regex_bytes(b),
+        ),
+        _ => equivalent_anchored(
+            Dfa::<256>::from_regex_bytes(a, Construction::Derivatives),
+            Dfa::<256>::from_regex_bytes(b, Construction::Derivatives),
+        ),
+    };
+    match difference {
+        Ok(None) => {
+            puts("equivalent\n");
+            exit(0);
+        }
+        Ok(Some(Difference::Counterexample(counterexample))) => {
+            let (matches, doesnt) = if counterexample.matched_by_a {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            puts("not equivalent: ");
+            put_quoted(counterexample.as_bytes());
+            puts(" matches ");
+            puts(matches);
+            puts(" but not ");
+            puts(doesnt);
+            puts("\n");
+            exit(1);
+        }
+        Ok(Some(Difference::Anchors)) => {
+            puts("not equivalent: ");
+            puts(a);
+            puts(" and ");
+            puts(b);
+            puts(" match the same strings, but are anchored differently\n");
+            exit(1);
+        }
+        Err(_) => {
+            eputs("equivalent: too large to compare\n");
+            exit(2);
+        }
+    }
+}
+
+// Print, as a transition table, the minimal DFA for the strings both patterns match (and), either
+// matches (or), or the first matches and the second doesn't (minus); or for `not`, the byte
+// strings the one pattern doesn't match.
+fn combine_command(args: Args) -> ! {
+    let (Some(operation), Some(a), b, None) = (args.get(2), args.get(3), args.get(4), args.get(5))
+    else {
+        usage();
+    };
+    let a = compile_nfa("combine", a);
+    let b = b.map(|b| compile_nfa("combine", b));
+    if b.as_ref().is_some_and(|b| b.anchors() != a.anchors()) {
+        eputs("combine: the patterns must be anchored the same way\n");
+        exit(2);
+    }
+
+    let a = Dfa::<256>::from_nfa(&a);
+    let b = b.as_ref().map(Dfa::<256>::from_nfa);
+    let dfa = match (operation, b) {
+        (b"and", Some(b)) => Dfa::<256>::intersection(&a, &b),
+        (b"or", Some(b)) => Dfa::<256>::union(&a, &b),
+        (b"minus", Some(b)) => Dfa::<256>::difference(&a, &b),
+        (b"not", None) => Dfa::<256>::complement(&a, &[(0x00, 0xff)]),
+        _ => usage(),
+    };
+    dfa.minimize().print_table();
+    exit(0);
+}
+
+// Print the strings a pattern matches, shortest first, one per line. By default, up to 100 of
+// them, of up to 8 bytes each.
+fn enumerate_command(args: Args) -> ! {
+    let Some(pattern) = args.get(2) else {
+        usage();
+    };
+    let max_len = args.get(3).map_or(Some(8), parse_number);
+    let count = args.get(4).map_or(Some(100), parse_number);
+    let (Some(max_len), Some(count), None) = (max_len, count, args.get(5)) else {
+        usage();
+    };
+    if max_len > MAX_WORD_LEN {
+        eputs("enumerate: max-len is at most ");
+        eputs(itoa(MAX_WORD_LEN as u32));
+        eputs("\n");
+        exit(2);
+    }
+
+    let dfa = Dfa::<256>::from_regex_bytes(pattern, Construction::for_pattern(pattern));
+    let mut found = false;
+    for word in dfa.enumerate(max_len).take(count) {
+        put_quoted(word.as_bytes());
+        puts("\n");
+        found = true;
+    }
+    exit(if found { 0 } else { 1 });
+}
+
+// Print a simpler pattern that matches the same strings, and how many states its Thompson NFA
+// takes next to the original's, before and after `Nfa::cleanup`.
+fn simplify_command(args: Args) -> ! {
+    let (Some(pattern), None) = (args.get(2), args.get(3)) else {
+        usage();
+    };
+    if Construction::for_pattern(pattern) == Construction::Derivatives {
+        eputs("simplify: \"&\" and \"~\" can't be simplified\n");
+        exit(2);
+    }
+
+    let simplified = Simplified::from_regex_bytes(pattern);
+    puts(simplified.pattern());
+    puts("\n");
+
+    let before = Nfa::<256>::from_regex_bytes(pattern);
+    let after = Nfa::<256>::from_regex_bytes(simplified.pattern());
+    puts(itoa(before.state_count() as u32));
+    puts(" => ");
+    puts(itoa(after.state_count() as u32));
+    puts(" NFA states, ");
+    puts(itoa(before.cleanup().state_count() as u32));
+    puts(" => ");
+    puts(itoa(after.cleanup().state_count() as u32));
+    puts(" after cleanup\n");
+    exit(0);
+}
+
+// What `generate` walks: the DFA, or if it doesn't fit, the NFA, which is slower but has no
+// such limit. A pattern with "&" or "~" has no NFA, so its DFA comes from derivatives.
+#[allow(clippy::large_enum_variant)]
+enum Walk {
+    Dfa(Dfa<256>),
+    Nfa(Nfa<256>),
+}
+
+impl Walk {
+    fn new(pattern: &'static [u8]) -> Self {
+        let construction = Construction::for_pattern(pattern);
+        if construction != Construction::Thompson {
+            return Self::Dfa(Dfa::from_regex_bytes(pattern, construction));
+        }
+        let nfa = Nfa::from_regex_bytes(pattern);
+        match Dfa::try_from_nfa(&nfa) {
+            Some(dfa) => Self::Dfa(dfa),
+            None => Self::Nfa(nfa),
+        }
+    }
+
+    fn generate(&self, rng: &mut Rng, near_miss: bool) -> Option<Word> {
+        match (self, near_miss) {
+            (Self::Dfa(dfa), false) => dfa.generate(rng, GENERATE_MAX_LEN),
+            (Self::Dfa(dfa), true) => dfa.generate_near_miss(rng, GENERATE_MAX_LEN),
+            (Self::Nfa(nfa), false) => nfa.generate(rng, GENERATE_MAX_LEN),
+            (Self::Nfa(nfa), true) => nfa.generate_near_miss(rng, GENERATE_MAX_LEN),
+        }
+    }
+}
+
+// Print random strings that a pattern matches, or with `near_miss`, that it just fails to
+// match; by default 10 of them. Without a seed, one is drawn from the kernel and printed to
+// stderr, so that a run that turns up a bug can be repeated.
+fn generate_command(args: Args, near_miss: bool) -> ! {
+    let Some(pattern) = args.get(2) else {
+        usage();
+    };
+    let count = args.get(3).map_or(Some(10), parse_number);
+    let seed = args.get(4).map(parse_number);
+    let (Some(count), None) = (count, args.get(5)) else {
+        usage();
+    };
+    let seed = match seed {
+        Some(Some(seed)) => seed as u64,
+        Some(None) => usage(),
+        None => {
+            let Some(seed) = entropy_seed() else {
+                eputs("generate: can't get a seed, pass one\n");
+                exit(2);
+            };
+            // Small enough to print and to type back in.
+            let seed = seed as u32;
+            eputs("seed: ");
+            eputs(itoa(seed));
+            eputs("\n");
+            seed as u64
+        }
+    };
+
+    let walk = Walk::new(pattern);
+    let mut rng = Rng::new(seed);
+    let mut found = false;
+    for _ in 0..count {
+        let word = walk.generate(&mut rng, near_miss);
+        let Some(word) = word else {
+            break;
+        };
+        put_quoted(word.as_bytes());
+        puts("\n");
+        found = true;
+    }
+    exit(if found { 0 } else { 1 });
+}
+
+// The longest line `grep` can look at.
+const MAX_LINE_LEN: usize = 4096;
+
+// How many states grep's lazy DFA may cache.
+const GREP_CACHE_STATES: usize = 64;
+
+// How grep looks for matches: with a forward and a reverse DFA if they fit in 256 states each,
+// and otherwise with a lazy DFA, which only builds the states the input gets to. A pattern with
+// "&" or "~" has no NFA for either, so its DFA comes from derivatives and restarts at every
+// position a match could start at.
+#[allow(clippy::large_enum_variant)]
+enum Search<'a> {
+    TwoPass(ReverseSearch<256>),
+    Lazy(LazyDfa<'a, 256, GREP_CACHE_STATES>),
+    Restarting(Dfa<256>),
+}
+
+impl<'a> Search<'a> {
+    fn new(nfa: &'a Nfa<256>) -> Self {
+        match ReverseSearch::new(nfa) {
+            Some(search) => Self::TwoPass(search),
+            None => Self::Lazy(LazyDfa::new(nfa)),
+        }
+    }
+
+    fn find(&mut self, haystack: &[u8]) -> Option<(usize, usize)> {
+        self.find_at(haystack, 0)
+    }
+
+    fn find_at(&mut self, haystack: &[u8], from: usize) -> Option<(usize, usize)> {
+        match self {
+            Self::TwoPass(search) => search.find_at(haystack, from),
+            Self::Lazy(lazy) => lazy.find_at(haystack, from),
+            Self::Restarting(dfa) => dfa.find_at(haystack, from),
+        }
+    }
+}
+
+// What to print for each file, from grep's options.
+#[derive(Clone, Copy, Default)]
+struct GrepOptions {
+    // -c: only how many lines were selected.
+    count: bool,
+    // -n: each line's number.
+    line_numbers: bool,
+    // -o: only the matches, each on its own line.
+    only_matching: bool,
+    // -v: select the lines that don't match.
+    invert: bool,
+    // Whether to start each line with the file's name: with more than one file.
+    file_names: bool,
+}
+
+// Print the lines of each file (or of stdin, for none or "-") that the pattern matches
+// somewhere in, the way POSIX grep does. Exits 0 if any line was selected and 1 if none was,
+// unless something went wrong, which is 2.
+fn grep_command(args: Args) -> ! {
+    let mut options = GrepOptions::default();
+    // -i: the pattern ignores case, as if it began with "(?i)".
+    let mut flags = Flags::NONE;
+    let mut idx = 2;
+    while let Some(arg) = args.get(idx) {
+        if arg == b"--" {
+            idx += 1;
+            break;
+        }
+        let [b'-', letters @ ..] = arg else {
+            break;
+        };
+        if letters.is_empty() {
+            break;
+        }
+        for letter in letters {
+            match letter {
+                b'c' => options.count = true,
+                b'i' => flags = flags.union(Flags::CASE_INSENSITIVE),
+                b'n' => options.line_numbers = true,
+                b'o' => options.only_matching = true,
+                b'v' => options.invert = true,
+                _ => usage(),
+            }
+        }
+        idx += 1;
+    }
+
+    let Some(pattern) = args.get(idx) else {
+        usage();
+    };
+    let nfa;
+    let mut search = if Construction::for_pattern(pattern) == Construction::Derivatives {
+        if flags != Flags::NONE {
+            eputs("grep: -i doesn't work with \"&\" and \"~\"; use (?i) in the pattern\n");
+            exit(2);
+        }
+        Search::Restarting(Dfa::from_regex_bytes(pattern, Construction::Derivatives))
+    } else {
+        nfa = Nfa::<256>::new().with_flags(flags).add_regex_bytes(pattern);
+        Search::new(&nfa)
+    };
+    let files = idx + 1..args.len();
+    options.file_names = files.len() > 1;
+
+    let mut selected = false;
+    let mut failed = false;
+    if files.is_empty() {
+        (selected, failed) = grep_file(&mut search, options, b"-");
+    }
+    for file in files {
+        let (file_selected, file_failed) = grep_file(&mut search, options, args.get(file).unwrap());
+        selected |= file_selected;
+        failed |= file_failed;
+    }
+
+    exit(if failed {
+        2
+    } else if selected {
+        0
+    } else {
+        1
+    });
+}
+
+// Grep one file, "-" being stdin. Returns whether any line was selected and whether anything
+// went wrong.
+fn grep_file(search: &mut Search, options: GrepOptions, name: &[u8]) -> (bool, bool) {
+    let (fd, name) = if name == b"-" {
+        (0, &b"(standard input)"[..])
+    } else {
+        match open(name) {
+            Some(fd) => (fd, name),
+            None => {
+                grep_error(name, "can't open");
+                return (false, true);
+            }
+        }
+    };
+
+    let mut input = InputBuffer::<4096>::new(fd);
+    let mut line = [0u8; MAX_LINE_LEN];
+    let mut line_number = 0;
+    let mut count = 0u32;
+    let mut failed = false;
+    while let Some(len) = input.read_line(&mut line) {
+        line_number += 1;
+        if len > MAX_LINE_LEN {
+            grep_error(name, "line too long, skipped");
+            failed = true;
+            continue;
+        }
+        let line = &line[0..len];
+
+        if search.find(line).is_some() == options.invert {
+            continue;
+        }
+        count += 1;
+        if options.count {
+            continue;
+        }
+
+        if !options.only_matching {
+            grep_prefix(options, name, line_number);
+            puts(line);
+            puts("\n");
+        } else if !options.invert {
+            // Every match that isn't empty, left to right.
+            let mut from = 0;
+            while let Some((start, end)) = search.find_at(line, from) {
+                if start == end {
+                    from = end + 1;
+                } else {
+                    grep_prefix(options, name, line_number);
+                    puts(&line[start..end]);
+                    puts("\n");
+                    from = end;
+                }
+                if from > line.len() {
+                    break;
+                }
+            }
+        }
+    }
+
+    if input.failed() {
+        grep_error(name, "read error");
+        failed = true;
+    }
+    if fd != 0 {
+        close(fd);
+    }
+
+    if options.count {
+        if options.file_names {
+            puts(name);
+            puts(":");
+        }
+        puts(itoa(count));
+        puts("\n");
+    }
+    (count > 0, failed)
+}
+
+fn grep_prefix(options: GrepOptions, name: &[u8], line_number: u32) {
+    if options.file_names {
+        puts(name);
+        puts(":");
+    }
+    if options.line_numbers {
+        puts(itoa(line_number));
+        puts(":");
+    }
+}
+
+fn grep_error(name: &[u8], message: &str) {
+    eputs("grep: ");
+    eputs(name);
+    eputs(": ");
+    eputs(message);
+    eputs("\n");
+}
+
+// Print where the leftmost-longest match of a pattern in the input is, as `Dfa::find` finds it
+// by restarting the DFA wherever a match could start, and check that the two searches grep uses
+// find the same span. Exits 0 on a match, 1 on none, and 2 if the searches disagree.
+fn find_command(args: Args) -> ! {
+    let (Some(pattern), Some(input), None) = (args.get(2), args.get(3), args.get(4)) else {
+        usage();
+    };
+
+    let nfa = compile_nfa("find", pattern);
+    let found = Dfa::<256>::from_nfa(&nfa).find(input);
+    match found {
+        Some((start, end)) => {
+            puts(itoa(start as u32));
+            puts("..");
+            puts(itoa(end as u32));
+            puts(": ");
+            put_quoted(&input[start..end]);
+            puts("\n");
+        }
+        None => {
+            puts("no match\n");
+        }
+    }
+
+    let two_pass = ReverseSearch::<256>::new(&nfa).map(|search| search.find(input));
+    let lazy = LazyDfa::<256, GREP_CACHE_STATES>::new(&nfa).find(input);
+    if two_pass.is_some_and(|two_pass| two_pass != found) || lazy != found {
+        eputs("find: grep's searches find a different match\n");
+        exit(2);
+    }
+    exit(if found.is_some() { 0 } else { 1 });
+}
+
+// Print each path that matches a shell glob: the paths given, or else each line of stdin. Exits
+// 0 if any matched.
+fn glob_command(args: Args) -> ! {
+    let Some(glob) = args.get(2) else {
+        usage();
+    };
+    let dfa = Dfa::<256>::from_nfa(&Nfa::<256>::from_glob_bytes(glob));
+
+    let mut any_matched = false;
+    let mut put_if_matches = |path: &[u8]| {
+        if dfa.matches(path) {
+            any_matched = true;
+            puts(path);
+            puts("\n");
+        }
+    };
+    if args.len() > 3 {
+        for idx in 3..args.len() {
+            put_if_matches(args.get(idx).unwrap());
+        }
+    } else {
+        let mut input = InputBuffer::<4096>::new(0);
+        let mut line = [0u8; MAX_LINE_LEN];
+        while let Some(len) = input.read_line(&mut line) {
+            if len <= MAX_LINE_LEN {
+                put_if_matches(&line[0..len]);
+            }
+        }
+    }
+    exit(if any_matched { 0 } else { 1 });
+}
+
+// Print the offsets of each match in a file, or stdin, as the input streams past, without
+// holding more than a buffer of it. A match marked "cut short" might have gone on further.
+// Exits 0 if there was a match.
+fn stream_command(args: Args) -> ! {
+    let (Some(pattern), None) = (args.get(2), args.get(4)) else {
+        usage();
+    };
+    let fd = match args.get(3) {
+        None | Some(b"-") => 0,
+        Some(name) => match open(name) {
+            Some(fd) => fd,
+            None => {
+                eputs("stream: ");
+                eputs(name);
+                eputs(": can't open\n");
+                exit(2);
+            }
+        },
+    };
+
+    let nfa = compile_nfa("stream", pattern);
+    let mut input = InputBuffer::<4096>::new(fd);
+    let mut any_matched = false;
+    for found in nfa.stream_matches(&mut input) {
+        any_matched = true;
+        puts(itoa(found.start));
+        puts("..");
+        puts(itoa(found.end));
+        puts(if found.cut_short {
+            " (cut short)\n"
+        } else {
+            "\n"
+        });
+    }
+
+    if fd != 0 {
+        close(fd);
+    }
+    if input.failed() {
+        eputs("stream: read error\n");
+        exit(2);
+    }
+    exit(if any_matched { 0 } else { 1 });
+}
+
+// Split the input into tokens of a small C-like language and print each one with the start
+// condition the scanner is left in. String literals (of printable ASCII) are scanned in an
+// exclusive condition, "#" directives up to the end of the line in an inclusive one, and
+// comments nest. Strings and comments push their condition and pop back to the one they were in.
+fn scan_command(args: Args) -> ! {
+    const IDENTIFIER: u16 = 1;
+    const NUMBER: u16 = 2;
+    const PUNCTUATION: u16 = 3;
+    const QUOTE: u16 = 4;
+    const TEXT: u16 = 5;
+    const ESCAPE: u16 = 6;
+    const HASH: u16 = 7;
+
+    let (Some(input), None) = (args.get(2), args.get(3)) else {
+        usage();
+    };
+
+    let mut scanner = Scanner::<256>::new()
+        .exclusive(b"STRING")
+        .exclusive(b"COMMENT")
+        .inclusive(b"DIRECTIVE")
+        .rule(b"[A-Za-z_][A-Za-z0-9_]*", Action::token(IDENTIFIER))
+        .rule(b"[0-9][0-9]*", Action::token(NUMBER))
+        .rule(b"[-+*/=<>;,(){}]", Action::token(PUNCTUATION))
+        .rule(b"#", Action::token(HASH).begin(b"DIRECTIVE"))
+        .rule(b"<DIRECTIVE>\\n", Action::SKIP.begin(INITIAL))
+        .rule(b"[ \t\n]", Action::SKIP)
+        .rule(b"\"", Action::token(QUOTE).push(b"STRING"))
+        .rule(b"<STRING>\"", Action::token(QUOTE).pop())
+        .rule(
+            b"<STRING>[ !#-\\[\\]-~][ !#-\\[\\]-~]*",
+            Action::token(TEXT),
+        )
+        .rule(b"<STRING>\\\\[\"\\\\n]", Action::token(ESCAPE))
+        .rule(b"<*>/\\*", Action::SKIP.push(b"COMMENT"))
+        .rule(b"<COMMENT>\\*/", Action::SKIP.pop())
+        .rule(b"<COMMENT>(?s).", Action::SKIP)
+        .build();
+
+    let mut cursor = Cursor::from(input);
+    while let Some(lexeme) = scanner.next_token(&mut cursor) {
+        puts(match lexeme.token {
+            IDENTIFIER => "IDENTIFIER",
+            NUMBER => "NUMBER",
+            PUNCTUATION => "PUNCTUATION",
+            QUOTE => "QUOTE",
+            TEXT => "TEXT",
+            ESCAPE => "ESCAPE",
+            HASH => "HASH",
+            UNMATCHED => "UNMATCHED",
+            _ => unreachable!(),
+        });
+        puts(" ");
+        put_quoted(&input[lexeme.start..lexeme.end]);
+        puts(" (");
+        puts(scanner.current());
+        puts(") @ ");
+        puts(itoa(lexeme.start as u32));
+        puts("\n");
+    }
+    exit(0);
+}
+
+// Print one DOT graph per step of running the pattern's NFA on the input, for `dot -O`.
+fn trace_command(args: Args) -> ! {
+    let (Some(pattern), Some(input), None) = (args.get(2), args.get(3), args.get(4)) else {
+        usage();
+    };
+
+    let accepted = compile_nfa("trace", pattern).trace(input);
+    exit(if accepted { 0 } else { 1 });
+}
+
+// Print whether the pattern matches the whole input, by backtracking, and how many steps that
+// took next to the one step per byte a DFA takes: `(a|a)*b` against a run of a's shows the
+// difference.
+fn backtrack_command(args: Args) -> ! {
+    let (Some(pattern), Some(input)) = (args.get(2), args.get(3)) else {
+        usage();
+    };
+    let budget = args.get(4).map_or(Some(BACKTRACK_BUDGET), parse_number);
+    let (Some(budget), None) = (budget, args.get(5)) else {
+        usage();
+    };
+
+    let nfa = compile_nfa("backtrack", pattern);
+    let mut backtracker = Backtracker::new(&nfa, budget);
+    let matched = match backtracker.matches(input) {
+        Ok(matched) => matched,
+        Err(BudgetExceeded) => {
+            eputs("backtrack: gave up after ");
+            eputs(itoa(budget as u32));
+            eputs(" steps\n");
+            exit(2);
+        }
+    };
+    puts(if matched { "match" } else { "no match" });
+    puts(" in ");
+    puts(itoa(backtracker.steps() as u32));
+    puts(" steps, where a DFA takes ");
+    puts(itoa(input.len() as u32));
+    puts("\n");
+    exit(if matched { 0 } else { 1 });
+}
+
+// Match each input with the lazy DFA, sharing one cache, then print what the cache did. Exits 0
+// if any input matched.
+fn lazy_command(args: Args) -> ! {
+    let (Some(pattern), true) = (args.get(2), args.len() > 3) else {
+        usage();
+    };
+
+    let nfa = compile_nfa("lazy", pattern);
+    let mut lazy = LazyDfa::<256, LAZY_CACHE_STATES>::new(&nfa);
+    let mut any_matched = false;
+    for idx in 3..args.len() {
+        let input = args.get(idx).unwrap();
+        let matched = lazy.matches(input);
+        any_matched |= matched;
+        put_quoted(input);
+        puts(if matched { ": match\n" } else { ": no match\n" });
+    }
+
+    let stats = lazy.stats();
+    puts("cached states: ");
+    puts(itoa(lazy.state_count() as u32));
+    puts(", misses: ");
+    puts(itoa(stats.misses as u32));
+    puts(", clears: ");
+    puts(itoa(stats.clears as u32));
+    puts(", fallbacks: ");
+    puts(itoa(stats.fallbacks as u32));
+    puts("\n");
+    exit(if any_matched { 0 } else { 1 });
+}
+
+// Print whether the whole input matches by taking one derivative of the pattern per byte, with
+// no automaton, and how many terms that built. Exits 0 on a match, 1 on none.
+fn derive_command(args: Args) -> ! {
+    let (Some(pattern), Some(input), None) = (args.get(2), args.get(3), args.get(4)) else {
+        usage();
+    };
+
+    let mut derivatives = Derivatives::from_regex_bytes(pattern);
+    let matched = derivatives.matches(input);
+    puts(if matched { "match" } else { "no match" });
+    puts(", with ");
+    puts(itoa(derivatives.term_count() as u32));
+    puts(" terms\n");
+    exit(if matched { 0 } else { 1 });
+}
+
+// The DFA columns of `sizes`: every construction but the first is checked against the first.
+const DFA_COLUMNS: [(&str, Construction); 4] = [
+    ("dfa", Construction::Thompson),
+    ("glushkov-dfa", Construction::Glushkov),
+    ("direct-dfa", Construction::Direct),
+    ("derivative-dfa", Construction::Derivatives),
+];
+
+// Print how many states each pattern takes, or each of the fixtures in main.rs without any, as
+// a table with a tab between columns:
+//
+//     nfa             Thompson's NFA
+//     cleaned         the same NFA after `Nfa::cleanup`
+//     glushkov        Glushkov's NFA
+//     dfa             the DFA from Thompson's NFA, not counting the dead state
+//     glushkov-dfa    the DFA from Glushkov's NFA
+//     direct-dfa      the DFA from followpos, with no NFA
+//     derivative-dfa  the DFA whose states are the pattern's derivatives
+//     minimal-dfa     the DFA with the fewest states
+//
+// The DFAs must all match the same strings; exits 1 if one doesn't.
+fn sizes_command(args: Args) -> ! {
+    puts("pattern\tnfa\tcleaned\tglushkov");
+    for (column, _) in DFA_COLUMNS {
+        puts("\t");
+        puts(column);
+    }
+    puts("\tminimal-dfa\n");
+
+    let mut agree = true;
+    if args.len() > 2 {
+        for idx in 2..args.len() {
+            let pattern = args.get(idx).unwrap();
+            agree &= put_sizes(pattern, pattern);
+        }
+    } else {
+        for (name, pattern) in crate::FIXTURES {
+            agree &= put_sizes(name, pattern);
+        }
+    }
+    exit(if agree { 0 } else { 1 });
+}
+
+// One row of `sizes`. Returns whether the DFAs agree.
+fn put_sizes(name: &[u8], pattern: &'static [u8]) -> bool {
+    let nfa = compile_nfa("sizes", pattern);
+    puts(name);
+    put_count(nfa.state_count() as usize);
+    put_count(nfa.cleanup().state_count() as usize);
+    put_count(Glushkov::<256>::from_regex_bytes(pattern).state_count() as usize);
+
+    let dfas =
+        DFA_COLUMNS.map(|(_, construction)| Dfa::<256>::from_regex_bytes(pattern, construction));
+    for dfa in &dfas {
+        put_count(dfa.state_count() as usize - 1);
+    }
+    put_count(dfas[0].minimize().state_count() as usize - 1);
+    puts("\n");
+
+    let mut agree = true;
+    for ((column, _), dfa) in DFA_COLUMNS.iter().zip(&dfas).skip(1) {
+        if let Ok(Some(_)) = equivalent_dfas(&dfas[0], dfa) {
+            eputs("sizes: ");
+            eputs(name);
+            eputs(": the ");
+            eputs(column);
+            eputs(" doesn't match the same strings as the dfa\n");
+            agree = false;
+        }
+    }
+    agree
+}
+
+// Print a pattern's NFA and its DFA side by side, as Graphviz DOT.
+fn dot_command(args: Args) -> ! {
+    let (Some(pattern), None) = (args.get(2), args.get(3)) else {
+        usage();
+    };
+
+    let nfa = compile_nfa("dot", pattern);
+    debug_print_side_by_side(&nfa, &Dfa::<256>::from_nfa(&nfa));
+    exit(0);
+}
+
+// Print a pattern's NFA and then its DFA as transition tables.
+fn table_command(args: Args) -> ! {
+    let (Some(pattern), None) = (args.get(2), args.get(3)) else {
+        usage();
+    };
+
+    let nfa = compile_nfa("table", pattern);
+    nfa.print_table();
+    puts("\n");
+    Dfa::<256>::from_nfa(&nfa).print_table();
+    exit(0);
+}
+
+// Write a pattern's NFA, or with -d its DFA, as JSON.
+fn json_command(args: Args) -> ! {
+    let (dfa, pattern, rest) = match args.get(2) {
+        Some(b"-d") => (true, args.get(3), args.get(4)),
+        pattern => (false, pattern, args.get(3)),
+    };
+    let (Some(pattern), None) = (pattern, rest) else {
+        usage();
+    };
+
+    let nfa = compile_nfa("json", pattern);
+    if dfa {
+        Dfa::<256>::from_nfa(&nfa).write_json(&mut Stdout);
+    } else {
+        nfa.write_json(&mut Stdout);
+    }
+    exit(0);
+}
+
+// Read an NFA, or with -d a DFA, as `json` writes it from stdin, and print its transition table.
+fn load_command(args: Args) -> ! {
+    let dfa = match (args.get(2), args.get(3)) {
+        (None, None) => false,
+        (Some(b"-d"), None) => true,
+        _ => usage(),
+    };
+
+    let mut input = InputBuffer::<4096>::new(0);
+    let loaded = if dfa {
+        Dfa::<256>::read_json(&mut input).map(|dfa| dfa.print_table())
+    } else {
+        Nfa::<256>::read_json(&mut input).map(|nfa| nfa.print_table())
+    };
+    if loaded.is_none() {
+        eputs(if dfa {
+            "load: not a DFA in JSON\n"
+        } else {
+            "load: not an NFA in JSON\n"
+        });
+        exit(2);
+    }
+    exit(0);
+}
+
+// The NFA for a pattern, for the subcommands that need one. A pattern with "&" or "~" under
+// (?b) has none: only derivatives can compile it.
+fn compile_nfa(command: &str, pattern: &'static [u8]) -> Nfa<256> {
+    if Construction::for_pattern(pattern) == Construction::Derivatives {
+        eputs(command);
+        eputs(": a pattern with \"&\" or \"~\" has no NFA\n");
+        exit(2);
+    }
+    Nfa::from_regex_bytes(pattern)
+}
+
+fn put_count(count: usize) {
+    puts("\t");
+    puts(itoa(count as u32));
+}
+
+fn parse_number(arg: &[u8]) -> Option<usize> {
+    if arg.is_empty() {
+        return None;
+    }
+    arg.iter().try_fold(0usize, |number, digit| match digit {
+        b'0'..=b'9' => number.checked_mul(10)?.checked_add((digit - b'0') as usize),
+        _ => None,
+    })
+}
+
+// A string in double quotes, with anything that isn't printable ASCII escaped.
+pub(crate) fn put_quoted(bytes: &[u8]) {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    puts("\"");
+    for byte in bytes {
+        match byte {
+            b'"' => puts("\\\""),
+            b'\\' => puts("\\\\"),
+            b'\n' => puts("\\n"),
+            b'\t' => puts("\\t"),
+            b' '..=b'~' => puts([*byte]),
+            _ => puts([
+                b'\\',
+                b'x',
+                HEX[(byte >> 4) as usize],
+                HEX[(byte & 0xf) as usize],
+            ]),
+        };
+    }
+    puts("\"");
+}
@@ -0,0 +1,126 @@
+use crate::io::{itoa, puts};
+
+// A tiny Graphviz DOT writer shared by the automaton printers.
+//
+// Node IDs are built from a caller-supplied prefix and a state index ("problem12"), so a prefix
+// must be a valid DOT ID on its own: letters, digits and underscores. Anything that ends up
+// between quotes goes through `Label`, which escapes it.
+
+pub(crate) fn begin_digraph(rankdir: &str) {
+    puts("digraph {\n");
+    puts("  rankdir=\"");
+    puts(rankdir);
+    puts("\";\n");
+}
+
+/// Label the whole graph; Graphviz puts it underneath.
+pub(crate) fn graph_label(label: impl FnOnce(Label)) {
+    puts("  label=\"");
+    label(Label);
+    puts("\";\n");
+}
+
+pub(crate) fn end_digraph() {
+    puts("}\n");
+}
+
+/// Open a cluster. Graphviz only draws a subgraph as a box (and only honors its `label`) when its
+/// name starts with "cluster".
+pub(crate) fn begin_cluster(prefix: &[u8]) {
+    puts("subgraph cluster_");
+    puts(prefix);
+    puts(" {\n");
+    puts("  label=\"");
+    Label.text(prefix);
+    puts("\";\n");
+}
+
+pub(crate) fn end_cluster() {
+    puts("}\n");
+}
+
+pub(crate) fn node(prefix: &[u8], idx: u32, attrs: &str, label: impl FnOnce(Label)) {
+    puts("  ");
+    puts(prefix);
+    puts(itoa(idx));
+    puts(" [label=\"");
+    label(Label);
+    puts("\"");
+    if !attrs.is_empty() {
+        puts(", ");
+        puts(attrs);
+    }
+    puts("];\n");
+}
+
+/// Draw the conventional arrow into the start state: an edge from an invisible point.
+pub(crate) fn start_arrow(prefix: &[u8], idx: u32) {
+    puts("  ");
+    puts(prefix);
+    puts("_start [shape=point, style=invis];\n");
+    puts("  ");
+    puts(prefix);
+    puts("_start -> ");
+    puts(prefix);
+    puts(itoa(idx));
+    puts(";\n");
+}
+
+pub(crate) fn edge(prefix: &[u8], from: u32, to: u32, label: impl FnOnce(Label)) {
+    puts("  ");
+    puts(prefix);
+    puts(itoa(from));
+    puts(" -> ");
+    puts(prefix);
+    puts(itoa(to));
+    puts(" [label=\"");
+    label(Label);
+    puts("\"];\n");
+}
+
+/// Writes the inside of a quoted DOT string.
+#[derive(Clone, Copy)]
+pub(crate) struct Label;
+
+impl Label {
+    /// Free-form text. Quotes and backslashes are escaped; a newline becomes a line break.
+    pub(crate) fn text<T: AsRef<[u8]>>(self, text: T) -> Self {
+        for byte in text.as_ref() {
+            match byte {
+                b'"' => puts("\\\""),
+                b'\\' => puts("\\\\"),
+                b'\n' => puts("\\n"),
+                _ => puts([*byte]),
+            };
+        }
+        self
+    }
+
+    pub(crate) fn number(self, number: u32) -> Self {
+        puts(itoa(number));
+        self
+    }
+
+    /// A single input symbol, drawn so that it is both valid DOT and readable: printable ASCII
+    /// as itself, the usual escapes for whitespace, and `\xNN` for everything else.
+    pub(crate) fn byte(self, byte: u8) -> Self {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        match byte {
+            b'"' => puts("\\\""),
+            b'\\' => puts("\\\\"),
+            b'\n' => puts("\\\\n"),
+            b'\t' => puts("\\\\t"),
+            b' '..=b'~' => puts([byte]),
+            _ => {
+                puts("\\\\x");
+                puts([HEX[(byte >> 4) as usize], HEX[(byte & 0xf) as usize]])
+            }
+        };
+        self
+    }
+
+    pub(crate) fn epsilon(self) -> Self {
+        puts("ε");
+        self
+    }
+}
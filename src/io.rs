@@ -1,4 +1,4 @@
-use crate::sys::syscall3;
+use crate::sys::{syscall3, syscall3_checked};
 use core::cmp::Ordering;
 use core::convert::From;
 
@@ -19,6 +19,61 @@ pub(crate) trait Read {
         self.ungetc();
         answer
     }
+
+    /// Read one UTF-8 encoded character. A byte that doesn't start a valid encoding, or a
+    /// sequence that stops short, comes back as U+FFFD, and only the bytes up to the problem are
+    /// consumed, so bad input never swallows the good input after it.
+    fn read_char(&mut self) -> Option<char> {
+        let lead = self.getc()?;
+        let (len, mut scalar, min) = match lead {
+            0x00..=0x7f => return Some(lead as char),
+            0xc2..=0xdf => (2, (lead & 0x1f) as u32, 0x80),
+            0xe0..=0xef => (3, (lead & 0x0f) as u32, 0x800),
+            0xf0..=0xf4 => (4, (lead & 0x07) as u32, 0x10000),
+            _ => return Some(char::REPLACEMENT_CHARACTER),
+        };
+
+        for _ in 1..len {
+            match self.getc() {
+                Some(byte) if byte & 0xc0 == 0x80 => scalar = scalar << 6 | (byte & 0x3f) as u32,
+                _ => {
+                    self.ungetc();
+                    return Some(char::REPLACEMENT_CHARACTER);
+                }
+            }
+        }
+
+        // Overlong encodings and surrogates are as bad as any other invalid byte.
+        Some(
+            char::from_u32(scalar)
+                .filter(|_| scalar >= min)
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        )
+    }
+
+    /// Read up to the next "\n", which is consumed but not stored, into `buf`. Returns the
+    /// length of the line, which is more than `buf.len()` if it didn't fit; the rest of it is
+    /// skipped. `None` once there are no more lines.
+    fn read_line(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let mut len = 0;
+        loop {
+            match self.getc() {
+                Some(b'\n') => return Some(len),
+                Some(byte) => {
+                    if len < buf.len() {
+                        buf[len] = byte;
+                    }
+                    len += 1;
+                }
+                None => {
+                    // The end, maybe after a last line without a "\n". Step back, so the next
+                    // call sees the end too.
+                    self.ungetc();
+                    return if len == 0 { None } else { Some(len) };
+                }
+            }
+        }
+    }
 }
 
 pub(crate) trait Write {
@@ -48,12 +103,10 @@ impl<'a, 'b: 'a> From<&'b [u8]> for Cursor<'a> {
 
 impl<'a> Read for Cursor<'a> {
     fn getc(&mut self) -> Option<u8> {
+        // Like InputBuffer, always advance -- even past the end -- so that every getc() can be
+        // undone by exactly one ungetc().
         self.offset += 1;
-        if self.offset == self.ptr.len() {
-            None
-        } else {
-            Some(self.ptr[self.offset - 1])
-        }
+        self.ptr.get(self.offset - 1).copied()
     }
 
     fn ungetc(&mut self) -> Option<()> {
@@ -86,11 +139,20 @@ impl<'a> Read for Cursor<'a> {
 /// We fill one side of the whole buffer at a time. Whenever the program calls getc(), it advances
 /// a cursor in the buffer. If the position of the cursor after the call is N/2 or N, we fill() the
 /// next side of the buffer: "side b" for N/2 and "side a" for N.
-struct InputBuffer<const N: usize> {
+///
+/// Only the first time, though: after ungetc() has backed over the line between the sides, the
+/// side ahead already holds the next input. This keeps the side behind the cursor intact, so
+/// the last N/2 - 1 bytes read can always be read again.
+///
+/// A read error ends the input, as the end of the file would; `failed` tells the two apart.
+pub(crate) struct InputBuffer<const N: usize> {
     buf: [u8; N],
     cursor: usize,
+    // The side to fill when the cursor next reaches it: 0 or N/2.
+    next_fill: usize,
     eofidx: usize,
     fd: usize,
+    failed: bool,
 }
 
 impl<const N: usize> InputBuffer<N> {
@@ -102,23 +164,51 @@ impl<const N: usize> InputBuffer<N> {
         N >> 1
     };
 
-    fn new(fd: usize) -> Self {
+    pub(crate) fn new(fd: usize) -> Self {
         let mut s = Self {
             fd,
             buf: [0u8; N],
             cursor: 0,
+            next_fill: 0,
             eofidx: N + 1,
+            failed: false,
         };
 
         s.fill();
         s
     }
 
+    /// How far ungetc() can always back up.
+    pub(crate) const MAX_UNGETC: usize = Self::MIDPOINT - 1;
+
+    /// Whether reading stopped early because of an error.
+    pub(crate) fn failed(&self) -> bool {
+        self.failed
+    }
+
     fn fill(&mut self) {
-        let mem = &mut self.buf[self.cursor..self.cursor + Self::MIDPOINT];
-        let read_bytes = unsafe { syscall3(3, self.fd, mem.as_mut_ptr() as usize, Self::MIDPOINT) };
+        self.next_fill = (self.cursor + Self::MIDPOINT) & (N - 1);
+        if self.eofidx <= N {
+            // Past the end there is nothing to read, and the end must stay where it is.
+            return;
+        }
+
+        // A pipe or a terminal hands over what it has so far, so a short read only means the
+        // end of the input once a read comes back empty.
+        let mut read_bytes = 0;
+        while read_bytes < Self::MIDPOINT {
+            let mem = &mut self.buf[self.cursor + read_bytes..self.cursor + Self::MIDPOINT];
+            match unsafe { syscall3_checked(3, self.fd, mem.as_mut_ptr() as usize, mem.len()) } {
+                Ok(0) => break,
+                Ok(count) => read_bytes += count,
+                Err(_) => {
+                    self.failed = true;
+                    break;
+                }
+            }
+        }
         if read_bytes < Self::MIDPOINT {
-            self.eofidx = self.cursor + read_bytes + 1;
+            self.eofidx = self.cursor + read_bytes;
         }
     }
 }
@@ -129,7 +219,7 @@ impl<const N: usize> Read for InputBuffer<N> {
         let last = self.cursor;
         self.cursor = (self.cursor + 1) & (N - 1);
 
-        if self.cursor == 0 || self.cursor == Self::MIDPOINT {
+        if self.cursor == self.next_fill {
             self.fill();
         }
 
@@ -278,8 +368,22 @@ impl<const N: usize> Write for OutputBuffer<N> {
     }
 }
 
+/// Standard output as a `Write`, for code that is generic over where its output goes. It shares
+/// its buffer with `puts`.
+pub(crate) struct Stdout;
+
+impl Write for Stdout {
+    fn putc(&mut self, ch: u8) -> usize {
+        puts([ch])
+    }
+
+    fn puts<T: AsRef<[u8]>>(&mut self, ch: T) -> usize {
+        puts(ch)
+    }
+}
+
 pub(crate) fn putc(ch: char) -> usize {
-    puts([ch as u8])
+    puts(ch.encode_utf8(&mut [0; 4]))
 }
 
 pub(crate) fn puts<T: AsRef<[u8]>>(ch: T) -> usize {
@@ -316,7 +420,7 @@ pub(crate) fn getc() -> Option<char> {
         }
     };
 
-    charbuf.getc().map(|xs| xs as char)
+    charbuf.read_char()
 }
 
 pub(crate) fn flush() {
@@ -326,15 +430,16 @@ pub(crate) fn flush() {
     }
 }
 
-pub(crate) fn itoa(input: u32) -> &'static str {
-    static mut OUTBUF: [u8; 16] = [0; 16];
+pub(crate) fn itoa(input: impl Into<u64>) -> &'static str {
+    // u64::MAX is 20 digits; the first byte is never written.
+    static mut OUTBUF: [u8; 21] = [0; 21];
 
+    let mut input = input.into();
     if input == 0 {
         unsafe { OUTBUF[0] = b'0' };
         return unsafe { ::core::str::from_utf8_unchecked(&OUTBUF[0..1]) };
     }
 
-    let mut input = input;
     let mut idx = unsafe { OUTBUF.len() } - 1;
     while input > 0 {
         let ch = (input % 10) as u8 + b'0';
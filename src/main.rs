@@ -4,12 +4,16 @@
 #![feature(const_slice_index)]
 #![feature(const_option)]
 
+mod cli;
+mod dot;
 mod io;
+mod random;
 mod regex;
+mod scanner;
 mod sys;
 
 use crate::io::{getc, putc, puts};
-use crate::sys::exit;
+use crate::sys::{exit, Args};
 
 /* expr ::= expr + term | expr - term | term
  * term ::= 0 | 1 | 2 | 3 | 4 | 5 | 6 | 7 | 8 | 9
@@ -76,20 +80,47 @@ fn term() {
     }
 }
 
+/// The patterns drawn by default, as (name, pattern).
+static FIXTURES: [(&[u8], &[u8]); 18] = [
+    (b"empty", b""),
+    (b"single_char", b"a"),
+    (b"product", b"ab"),
+    (b"product_five", b"apple"),
+    (b"kleene", b"a*"),
+    (b"kleene_product", b"ab*"),
+    // product + kleene star on last char
+    (b"product_five_kleene", b"apple*"),
+    (b"multi_product_multi_kleene", b"ap*le*"),
+    (b"alternate", b"apple|banana"),
+    (b"alternate_kleene", b"apple|banana*"),
+    (b"alternate_interstitial_kleene", b"ap*le|bana*na"),
+    (b"alternate_alternate", b"apple|banana|cat"),
+    (b"product_group_product", b"wow(apple)cat"),
+    (b"group_alternate_concat", b"(apple|banana) cat"),
+    (b"group_alternate_star", b"(apple|banana)*"),
+    (b"group_alternate_alternate", b"(apple|banana)|cat"),
+    (b"case_insensitive", b"(?i)apple"),
+    (b"dot", b"a.c"),
+];
+
 #[no_mangle]
 /// # Safety
 /// ... is an illusion
-pub unsafe extern "C" fn main() {
+pub unsafe extern "C" fn main(argc: i32, argv: *const *const u8) {
     use crate::regex::Nfa;
 
-    puts("digraph {\n");
-    puts("rankdir=\"TB\";\n");
+    let args = Args::new(argc, argv);
+    if args.len() > 1 {
+        cli::run(args);
+    }
+
+    dot::begin_digraph("LR");
 
     #[cfg(any())]
     {
         let nfa = Nfa::<256>::from_regex_bytes(b"a(b|c)");
         nfa.debug_print(b"problem");
-        puts("}\n");
+        dot::end_digraph();
         exit(1);
     }
 
@@ -97,7 +128,7 @@ pub unsafe extern "C" fn main() {
     {
         let nfa = Nfa::<256>::from_regex_bytes(b"a(b|c)*");
         nfa.debug_print(b"problem");
-        puts("}\n");
+        dot::end_digraph();
         exit(1);
     }
 
@@ -105,59 +136,14 @@ pub unsafe extern "C" fn main() {
     {
         let nfa = Nfa::<256>::from_regex_bytes(b"(x|y|z)*");
         nfa.debug_print(b"problem");
-        puts("}\n");
+        dot::end_digraph();
         exit(1);
     }
 
-    let nfa = Nfa::<256>::from_regex_bytes(b"");
-    nfa.debug_print(b"empty");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"a");
-    nfa.debug_print(b"single_char");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"ab");
-    nfa.debug_print(b"product");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"apple");
-    nfa.debug_print(b"product_five");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"a*");
-    nfa.debug_print(b"kleene");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"ab*");
-    nfa.debug_print(b"kleene_product");
-
-    // product + kleene star on last char
-    let nfa = Nfa::<256>::from_regex_bytes(b"apple*");
-    nfa.debug_print(b"product_five_kleene");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"ap*le*");
-    nfa.debug_print(b"multi_product_multi_kleene");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"apple|banana");
-    nfa.debug_print(b"alternate");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"apple|banana*");
-    nfa.debug_print(b"alternate_kleene");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"ap*le|bana*na");
-    nfa.debug_print(b"alternate_interstitial_kleene");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"apple|banana|cat");
-    nfa.debug_print(b"alternate_alternate");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"wow(apple)cat");
-    nfa.debug_print(b"product_group_product");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"(apple|banana) cat");
-    nfa.debug_print(b"group_alternate_concat");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"(apple|banana)*");
-    nfa.debug_print(b"group_alternate_star");
-
-    let nfa = Nfa::<256>::from_regex_bytes(b"(apple|banana)|cat");
-    nfa.debug_print(b"group_alternate_alternate");
-    puts("}\n");
+    for (name, pattern) in FIXTURES {
+        Nfa::<256>::from_regex_bytes(pattern).debug_print(name);
+    }
+    dot::end_digraph();
 
     exit(1);
     puts("hello world\n");
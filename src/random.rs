@@ -0,0 +1,47 @@
+use crate::sys::getentropy;
+
+/// A seedable pseudo-random number generator (SplitMix64): the same seed always gives the same
+/// numbers, which is what we want for fuzzing, where a failure has to be replayed. Not for
+/// anything that needs to be unpredictable.
+#[derive(Clone, Copy)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`, which must not be empty.
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        if bound == 0 {
+            panic!("can't pick a number below 0");
+        }
+        // The high half of a 64x64-bit product is close enough to uniform for our bounds.
+        ((self.next_u64() as u128 * bound as u128) >> 64) as usize
+    }
+
+    /// A byte in `lo..=hi`.
+    pub(crate) fn byte_between(&mut self, lo: u8, hi: u8) -> u8 {
+        lo + self.below((hi - lo) as usize + 1) as u8
+    }
+}
+
+/// A seed from the operating system's entropy source, for when the caller has none to replay.
+pub(crate) fn entropy_seed() -> Option<u64> {
+    let mut bytes = [0u8; 8];
+    if getentropy(&mut bytes) {
+        Some(u64::from_le_bytes(bytes))
+    } else {
+        None
+    }
+}
@@ -1,12 +1,49 @@
+mod backtrack;
+mod classes;
+mod cleanup;
+mod derivative;
+mod enumerate;
+mod equivalence;
+mod followpos;
+mod generate;
+mod glob;
+mod glushkov;
+mod json;
+mod lazy;
+mod minimize;
+mod product;
+mod reverse;
+mod simplify;
+mod stream;
+mod syntax;
+mod table;
+mod trace;
+mod utf8;
+
+pub(crate) use backtrack::{Backtracker, BudgetExceeded};
+use classes::ByteClasses;
+pub(crate) use derivative::Derivatives;
+pub(crate) use enumerate::{Word, MAX_WORD_LEN};
+pub(crate) use equivalence::{equivalent, equivalent_anchored, equivalent_dfas, Difference};
+pub(crate) use glushkov::Glushkov;
+pub(crate) use lazy::LazyDfa;
+pub(crate) use reverse::ReverseSearch;
+pub(crate) use simplify::Simplified;
+use syntax::{read_symbol, Node, Syntax, MAX_NODES};
+
 const TRANSITIONS_PER_STATE: usize = 4;
 
+// How many characters and ranges one bracketed class may list.
+const MAX_CLASS_RANGES: usize = 32;
+
 // Depending on the N parameter to NFA, we can use smaller types to represent indices.
-type NFASize = u8;
-type DFASize = u8;
+pub(crate) type NFASize = u8;
+pub(crate) type DFASize = u8;
 
 #[derive(Clone, Copy, Default)]
 struct Transition {
-    on_character: Option<u8>,
+    // An inclusive range of bytes, or None for ε.
+    on_range: Option<(u8, u8)>,
     to_state_idx: NFASize,
 }
 
@@ -21,7 +58,7 @@ impl Default for State {
         Self {
             transition_count: 0,
             transitions: [Transition {
-                on_character: None,
+                on_range: None,
                 to_state_idx: 0,
             }; TRANSITIONS_PER_STATE],
         }
@@ -29,27 +66,198 @@ impl Default for State {
 }
 
 impl State {
-    const fn add_transition(mut self, on_character: Option<u8>, to_state_idx: NFASize) -> Self {
-        self.transitions[self.transition_count as usize].on_character = on_character;
+    const fn add_transition(mut self, on_range: Option<(u8, u8)>, to_state_idx: NFASize) -> Self {
+        self.transitions[self.transition_count as usize].on_range = on_range;
         self.transitions[self.transition_count as usize].to_state_idx = to_state_idx;
         self.transition_count += 1;
         self
     }
 }
 
+/// Matching options. Pass them to `Nfa::with_flags`, or turn them on and off inside a pattern
+/// with `(?i)`, `(?-i)`, `(?is:...)` and so on.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Flags(u8);
+
+impl Flags {
+    pub(crate) const NONE: Self = Self(0);
+    /// `i`: letters match in either case.
+    pub(crate) const CASE_INSENSITIVE: Self = Self(1);
+    /// `s`: `.` matches "\n", too.
+    pub(crate) const DOT_MATCHES_NEWLINE: Self = Self(2);
+    /// `m`: `^` and `$` match at the start and end of every line, not just of the whole input.
+    pub(crate) const MULTILINE: Self = Self(4);
+    /// `b`: `&` and `~` are the boolean operators "and" and "not" instead of standing for
+    /// themselves. Only `Construction::Derivatives` can compile them.
+    pub(crate) const SET_OPERATORS: Self = Self(8);
+
+    pub(crate) const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// Where a pattern's `^` or `$` lets a match begin or end. Like lex, we only accept anchors at
+/// the very start and end of a pattern, so they never have to live inside the automaton: they
+/// only matter when searching for a match inside a longer input (`Dfa::find`).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Anchor {
+    None,
+    /// `^` or `$`: only at the start (end) of the input.
+    Text,
+    /// `^` or `$` under `(?m)`: also right after (before) a "\n".
+    Line,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Anchors {
+    pub(crate) start: Anchor,
+    pub(crate) end: Anchor,
+}
+
+impl Anchors {
+    pub(crate) const NONE: Self = Self {
+        start: Anchor::None,
+        end: Anchor::None,
+    };
+
+    const fn for_flags(flags: Flags) -> Anchor {
+        if flags.contains(Flags::MULTILINE) {
+            Anchor::Line
+        } else {
+            Anchor::Text
+        }
+    }
+
+    /// Whether a match may start at `idx` in `haystack`.
+    pub(crate) fn can_start_at(&self, haystack: &[u8], idx: usize) -> bool {
+        match self.start {
+            Anchor::None => true,
+            Anchor::Text => idx == 0,
+            Anchor::Line => idx == 0 || haystack[idx - 1] == b'\n',
+        }
+    }
+
+    /// Whether a match may end at `idx` in `haystack`.
+    pub(crate) fn can_end_at(&self, haystack: &[u8], idx: usize) -> bool {
+        match self.end {
+            Anchor::None => true,
+            Anchor::Text => idx == haystack.len(),
+            Anchor::Line => idx == haystack.len() || haystack[idx] == b'\n',
+        }
+    }
+}
+
 /// # NFA: Nondeterministic Finite Automaton
 pub(crate) struct Nfa<const N: usize> {
     states: [State; N],
     state_count: NFASize,
     accept_idx: NFASize,
     start_idx: NFASize,
+    // The flags in effect at this point of the parse.
+    flags: Flags,
+    anchors: Anchors,
 }
 
-pub(crate) struct Dfa<const N: usize> {
-    states: [(u8, DFASize); N],
+/// A set of automaton states, one bit per possible state index. Used both for sets of NFA states
+/// (the "T" of the subset construction) and for sets of DFA states.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct StateSet {
+    bits: [u64; 4],
+}
+
+impl StateSet {
+    pub(crate) const fn new() -> Self {
+        Self { bits: [0; 4] }
+    }
+
+    pub(crate) const fn insert(&mut self, idx: u8) {
+        self.bits[idx as usize >> 6] |= 1 << (idx & 63);
+    }
+
+    pub(crate) const fn contains(&self, idx: u8) -> bool {
+        self.bits[idx as usize >> 6] & (1 << (idx & 63)) != 0
+    }
+
+    pub(crate) const fn is_empty(&self) -> bool {
+        self.bits[0] | self.bits[1] | self.bits[2] | self.bits[3] == 0
+    }
+
+    pub(crate) fn union(self, other: Self) -> Self {
+        let mut bits = self.bits;
+        for (word, other) in bits.iter_mut().zip(other.bits) {
+            *word |= other;
+        }
+        Self { bits }
+    }
+
+    pub(crate) fn intersection(self, other: Self) -> Self {
+        let mut bits = self.bits;
+        for (word, other) in bits.iter_mut().zip(other.bits) {
+            *word &= other;
+        }
+        Self { bits }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=255u8).filter(|idx| self.contains(*idx))
+    }
+}
+
+/// # DFA: Deterministic Finite Automaton
+///
+/// Built from an NFA by the subset construction. Each DFA state stands for the set of NFA states
+/// the NFA could be in after reading the same input; we keep that set around in `nfa_states`.
+///
+/// State 0 is always the dead state (the empty set of NFA states). Every missing transition
+/// points at it, so a zeroed transition table is a table full of dead ends.
+///
+/// The transition table has a column per byte class (see `ByteClasses`), not per byte, and is
+/// stored flat: a row of `classes.count()` cells per state, one after the other. `C` is how many
+/// cells it has room for, so a pattern with few classes can have up to `N` states and one with
+/// many classes fewer; one that needs more than that panics.
+pub(crate) struct Dfa<const N: usize, const C: usize = 16384> {
+    transitions: [DFASize; C],
+    classes: ByteClasses,
+    nfa_states: [StateSet; N],
+    accepting: StateSet,
     state_count: DFASize,
-    accept_idx: DFASize,
-    start_id: DFASize,
+    marked_count: DFASize,
+    start_idx: DFASize,
+    anchors: Anchors,
+}
+
+/// The ways from a pattern to an automaton; see `Dfa::from_regex_bytes`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Construction {
+    /// Thompson's construction (`Nfa`), then the subset construction.
+    Thompson,
+    /// Glushkov's ε-free NFA with one state per position (`Glushkov`), then the subset
+    /// construction.
+    Glushkov,
+    /// No NFA at all: DFA states are sets of positions, from followpos.
+    Direct,
+    /// No NFA either: DFA states are Brzozowski derivatives of the pattern. The only one that
+    /// takes "&" and "~" under (?b).
+    Derivatives,
+}
+
+impl Construction {
+    /// Thompson's, unless `input` has "&" or "~" under (?b), which only derivatives can compile.
+    pub(crate) fn for_pattern(input: &[u8]) -> Self {
+        if Syntax::from_regex_bytes(input).has_set_operators() {
+            Self::Derivatives
+        } else {
+            Self::Thompson
+        }
+    }
 }
 
 impl<const N: usize> Default for Nfa<N> {
@@ -59,11 +267,13 @@ impl<const N: usize> Default for Nfa<N> {
             state_count: 0,
             start_idx: 0,
             accept_idx: 0,
+            flags: Flags::NONE,
+            anchors: Anchors::NONE,
         }
     }
 }
 
-fn ε_closure<const N: usize>(_n: &Nfa<N>) -> Dfa<N> {
+fn ε_closure<const N: usize>(nfa: &Nfa<N>, set: StateSet) -> StateSet {
     /*
      * T is a set of NFA states
      * push all states in T onto stack
@@ -79,147 +289,457 @@ fn ε_closure<const N: usize>(_n: &Nfa<N>) -> Dfa<N> {
      *   done
      * done
      */
-    Dfa {
-        states: [Default::default(); N],
-        state_count: 0,
-        accept_idx: 0,
-        start_id: 0,
+    let mut stack = [0 as NFASize; N];
+    let mut top = 0;
+    for t in set.iter() {
+        stack[top] = t;
+        top += 1;
     }
+
+    let mut closure = set;
+    while top > 0 {
+        top -= 1;
+        let state = &nfa.states[stack[top] as usize];
+        for transition in &state.transitions[0..state.transition_count as usize] {
+            let u = transition.to_state_idx;
+            if transition.on_range.is_none() && !closure.contains(u) {
+                closure.insert(u);
+                stack[top] = u;
+                top += 1;
+            }
+        }
+    }
+
+    closure
 }
 
-// Input: a regular expression r over an alphabet Σ
-// Output: an NFA N accepting L(r)
-impl<const N: usize> Nfa<N> {
-    pub(crate) const fn from_regex_bytes(input: &'static [u8]) -> Self {
-        // I sure wish we could use Default::default() in const functions.
-        let nfa = Self {
-            states: [State {
-                transition_count: 0,
-                transitions: [Transition {
-                    on_character: None,
-                    to_state_idx: 0,
-                }; TRANSITIONS_PER_STATE],
-            }; N],
-            state_count: 0,
-            start_idx: 0,
-            accept_idx: 0,
-        };
+// move(T, a): the set of NFA states reachable from some state in T on a transition labeled a.
+fn r#move<const N: usize>(nfa: &Nfa<N>, set: &StateSet, chara: u8) -> StateSet {
+    let mut result = StateSet::new();
+    for t in set.iter() {
+        let state = &nfa.states[t as usize];
+        for transition in &state.transitions[0..state.transition_count as usize] {
+            if let Some((lo, hi)) = transition.on_range {
+                if lo <= chara && chara <= hi {
+                    result.insert(transition.to_state_idx);
+                }
+            }
+        }
+    }
+    result
+}
 
-        let (nfa, idx) = nfa.expr(input, 0);
-        if idx != input.len() {
-            panic!("unexpected character");
+impl<const N: usize, const C: usize> Dfa<N, C> {
+    pub(crate) const DEAD: DFASize = 0;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            transitions: [Self::DEAD; C],
+            classes: ByteClasses::new(),
+            nfa_states: [StateSet::new(); N],
+            accepting: StateSet::new(),
+            state_count: 1,
+            marked_count: 1,
+            start_idx: Self::DEAD,
+            anchors: Anchors::NONE,
         }
+    }
 
-        nfa
+    pub(crate) fn from_nfa<const M: usize>(nfa: &Nfa<M>) -> Self {
+        match Self::try_from_nfa(nfa) {
+            Some(dfa) => dfa,
+            None => panic!("too many DFA states"),
+        }
     }
 
-    /* Language:
-     *
-     *     expr     -> term rest
-     *     rest     -> "|" expr
-     *               | "(" expr ")" postfix
-     *               | term
-     *     term     -> "\\" <any> postfix
-     *               | 𝛼 in Σ postfix
-     *               | ε
-     *     postfix -> "*"
-     *               | ε
-     */
-    const fn expr(mut self, input: &'static [u8], mut idx: usize) -> (Self, usize) {
-        (self, idx) = self.term(input, idx);
-        while idx < input.len() {
-            let last_idx = idx;
-
-            (self, idx) = self.rest(input, idx);
-            if last_idx == idx {
-                return (self, idx);
+    /// Like `from_nfa`, but `None` instead of a panic when the DFA needs more than `N` states.
+    pub(crate) fn try_from_nfa<const M: usize>(nfa: &Nfa<M>) -> Option<Self> {
+        let mut dfa = Self::new();
+        dfa.start_idx = dfa.try_add_start(nfa, nfa.start_idx)?;
+        dfa.anchors = nfa.anchors;
+        Some(dfa)
+    }
+
+    /// Compile `input` straight to a DFA, by way of whichever NFA `construction` says.
+    pub(crate) fn from_regex_bytes(input: &'static [u8], construction: Construction) -> Self {
+        match construction {
+            Construction::Thompson => Self::from_nfa(&Nfa::<256>::from_regex_bytes(input)),
+            Construction::Glushkov => {
+                Self::from_glushkov(&Glushkov::<256>::from_regex_bytes(input))
+            }
+            Construction::Direct => Self::from_regex_bytes_direct(input),
+            Construction::Derivatives => {
+                Self::from_derivatives(&mut Derivatives::from_regex_bytes(input))
             }
         }
+    }
 
-        (self, input.len())
+    /// Determinize everything reachable from `nfa_start` into this DFA and return the DFA state
+    /// standing for ε_closure({nfa_start}). Calling this more than once gives a single DFA with
+    /// several entry points that share every state they have in common.
+    ///
+    /// This is the subset construction from "Compilers: Principles, Tools, and Techniques":
+    ///
+    ///     initially, ε_closure(s0) is the only state in Dstates, and it is unmarked
+    ///     while there is an unmarked state T in Dstates; do
+    ///       mark T
+    ///       for each input symbol a; do
+    ///         U := ε_closure(move(T, a))
+    ///         if U is not in Dstates; do
+    ///           add U as an unmarked state to Dstates
+    ///         done
+    ///         Dtran[T, a] := U
+    ///       done
+    ///     done
+    ///
+    /// States are numbered in the order they are discovered, so the unmarked states are always
+    /// exactly `marked_count..state_count`.
+    ///
+    /// The "input symbols" are byte classes, which the first call works out from the whole of
+    /// `nfa`; later calls must pass the same NFA, give or take ε-transitions.
+    pub(crate) fn add_start<const M: usize>(
+        &mut self,
+        nfa: &Nfa<M>,
+        nfa_start: NFASize,
+    ) -> DFASize {
+        match self.try_add_start(nfa, nfa_start) {
+            Some(start_idx) => start_idx,
+            None => panic!("too many DFA states"),
+        }
     }
 
-    const fn term(mut self, input: &'static [u8], mut idx: usize) -> (Self, usize) {
-        if idx >= input.len() {
-            return (self, idx);
+    // Like `add_start`, but `None` instead of a panic when the DFA runs out of room for states.
+    fn try_add_start<const M: usize>(
+        &mut self,
+        nfa: &Nfa<M>,
+        nfa_start: NFASize,
+    ) -> Option<DFASize> {
+        if self.state_count == 1 {
+            self.classes = ByteClasses::from_nfa(nfa);
+            if self.classes.count() > C {
+                panic!("too many byte classes");
+            }
         }
 
-        (self, idx) = match input[idx] {
-            b'\\' => self.escaped_term(input, idx + 1),
-            chara @ (b'a'..=b'z'
-            | b'A'..=b'Z'
-            | b'0'..=b'9'
-            | b'!'
-            | b'@'
-            | b'#'
-            | b'%'
-            | b'&'
-            | b'-'
-            | b'='
-            | b'+'
-            | b';'
-            | b':'
-            | b'"'
-            | b','
-            | b'<'
-            | b'>'
-            | b'/'
-            | b'`'
-            | b'~'
-            | b' '
-            | b'\'') => (self.add_alphabet_term(chara), idx + 1),
+        let mut seed = StateSet::new();
+        seed.insert(nfa_start);
+        let seed = ε_closure(nfa, seed);
+        let start_idx = self.try_intern(seed, seed.contains(nfa.accept_idx))?;
 
-            _ => return (self.add_empty_term(), idx),
-        };
+        while self.marked_count < self.state_count {
+            let t = self.marked_count;
+            self.marked_count += 1;
 
-        self.postfix(input, idx)
+            for class in 0..self.classes.count() {
+                // Every byte in a class moves the same way, so any one of them will do.
+                let (chara, _) = self.classes.range(class as u8);
+                let u = r#move(nfa, &self.nfa_states[t as usize], chara);
+                if u.is_empty() {
+                    continue;
+                }
+                let u = ε_closure(nfa, u);
+                let u = self.try_intern(u, u.contains(nfa.accept_idx))?;
+                self.set_transition(t, class, u);
+            }
+        }
+
+        Some(start_idx)
     }
 
-    const fn postfix(self, input: &'static [u8], idx: usize) -> (Self, usize) {
-        if idx >= input.len() {
-            return (self, idx);
+    // The DFA state for `set`, added if it's new; `accepting` says whether it should accept.
+    fn intern(&mut self, set: StateSet, accepting: bool) -> DFASize {
+        match self.try_intern(set, accepting) {
+            Some(idx) => idx,
+            None => panic!("too many DFA states"),
         }
+    }
 
-        if b'*' == input[idx] {
-            return (self.kleene_star(), idx + 1);
+    // Like `intern`, but `None` when there's no room for another state.
+    fn try_intern(&mut self, set: StateSet, accepting: bool) -> Option<DFASize> {
+        if set.is_empty() {
+            return Some(Self::DEAD);
         }
 
-        (self, idx)
+        for idx in 1..self.state_count {
+            if self.nfa_states[idx as usize] == set {
+                return Some(idx);
+            }
+        }
+
+        if !self.has_room() {
+            return None;
+        }
+
+        let idx = self.state_count;
+        self.state_count += 1;
+        self.nfa_states[idx as usize] = set;
+        if accepting {
+            self.accepting.insert(idx);
+        }
+        Some(idx)
+    }
+
+    // Whether another state fits: another row of the table, as well as fewer than `N` states.
+    fn has_room(&self) -> bool {
+        let rows = self.state_count as usize + 1;
+        rows <= N && self.state_count < DFASize::MAX && rows * self.classes.count() <= C
+    }
+
+    // The transitions out of `state`, one per byte class.
+    fn row(&self, state: DFASize) -> &[DFASize] {
+        let start = state as usize * self.classes.count();
+        &self.transitions[start..start + self.classes.count()]
+    }
+
+    fn transition(&self, state: DFASize, class: usize) -> DFASize {
+        self.transitions[state as usize * self.classes.count() + class]
+    }
+
+    fn set_transition(&mut self, state: DFASize, class: usize, target: DFASize) {
+        self.transitions[state as usize * self.classes.count() + class] = target;
+    }
+
+    pub(crate) fn state_count(&self) -> DFASize {
+        self.state_count
+    }
+
+    pub(crate) fn step(&self, state: DFASize, chara: u8) -> DFASize {
+        self.transition(state, self.classes.class(chara) as usize)
+    }
+
+    /// The maximal runs of bytes that take `state` somewhere other than the dead state, in
+    /// ascending order, with where they go.
+    pub(crate) fn byte_runs(&self, state: DFASize) -> impl Iterator<Item = (u8, u8, DFASize)> + '_ {
+        let row = self.row(state);
+        let mut class = 0;
+        core::iter::from_fn(move || {
+            while class < self.classes.count() && row[class] == Self::DEAD {
+                class += 1;
+            }
+            if class == self.classes.count() {
+                return None;
+            }
+            let target = row[class];
+            let (lo, mut hi) = self.classes.range(class as u8);
+            class += 1;
+            // Neighbouring classes can go to the same place; draw them as one run.
+            while class < self.classes.count() && row[class] == target {
+                hi = self.classes.range(class as u8).1;
+                class += 1;
+            }
+            Some((lo, hi, target))
+        })
+    }
+
+    pub(crate) fn is_accepting(&self, state: DFASize) -> bool {
+        self.accepting.contains(state)
     }
 
-    const fn rest(self, input: &'static [u8], idx: usize) -> (Self, usize) {
-        let (mut nfa, mut idx) = (self, idx);
+    /// The set of NFA states this DFA state was built from.
+    pub(crate) fn nfa_states(&self, state: DFASize) -> &StateSet {
+        &self.nfa_states[state as usize]
+    }
 
-        let last_start_idx = nfa.start_idx;
-        let last_accept_idx = nfa.accept_idx;
-        if idx >= input.len() {
-            (nfa, idx) = nfa.term(input, idx);
-            return (nfa.product(last_start_idx, last_accept_idx), idx);
+    pub(crate) fn matches(&self, input: &[u8]) -> bool {
+        let mut state = self.start_idx;
+        for chara in input {
+            state = self.step(state, *chara);
+            if state == Self::DEAD {
+                return false;
+            }
         }
-        match input[idx] {
-            b'(' => nfa.group(input, idx),
-            b'|' => nfa.alternate(input, idx),
-            _ => {
-                (nfa, idx) = nfa.term(input, idx);
-                (nfa.product(last_start_idx, last_accept_idx), idx)
+        self.is_accepting(state)
+    }
+
+    /// Find the leftmost-longest match anywhere in `haystack`, honoring the pattern's anchors.
+    /// This simply restarts the DFA at every position that a match may start at.
+    pub(crate) fn find(&self, haystack: &[u8]) -> Option<(usize, usize)> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Like `find`, but only for matches that start at `from` or later; see
+    /// `ReverseSearch::find_at`.
+    pub(crate) fn find_at(&self, haystack: &[u8], from: usize) -> Option<(usize, usize)> {
+        for start in from..=haystack.len() {
+            if !self.anchors.can_start_at(haystack, start) {
+                continue;
+            }
+
+            let mut state = self.start_idx;
+            let mut end = None;
+            let mut idx = start;
+            loop {
+                if self.is_accepting(state) && self.anchors.can_end_at(haystack, idx) {
+                    end = Some(idx);
+                }
+                if idx == haystack.len() {
+                    break;
+                }
+                state = self.step(state, haystack[idx]);
+                if state == Self::DEAD {
+                    break;
+                }
+                idx += 1;
+            }
+
+            if let Some(end) = end {
+                return Some((start, end));
             }
         }
+
+        None
+    }
+}
+
+// Input: a regular expression r over an alphabet Σ
+// Output: an NFA N accepting L(r)
+impl<const N: usize> Nfa<N> {
+    pub(crate) const fn from_regex_bytes(input: &[u8]) -> Self {
+        Self::new().add_regex_bytes(input)
     }
 
-    const fn escaped_term(self, input: &'static [u8], idx: usize) -> (Self, usize) {
-        if idx >= input.len() {
-            panic!("unexpected end of input: expected escaped character");
+    pub(crate) const fn new() -> Self {
+        // I sure wish we could use Default::default() in const functions.
+        Self {
+            states: [State {
+                transition_count: 0,
+                transitions: [Transition {
+                    on_range: None,
+                    to_state_idx: 0,
+                }; TRANSITIONS_PER_STATE],
+            }; N],
+            state_count: 0,
+            start_idx: 0,
+            accept_idx: 0,
+            flags: Flags::NONE,
+            anchors: Anchors::NONE,
         }
+    }
 
-        match input[idx] {
-            b'n' => (self.add_alphabet_term(b'\n'), idx + 1),
-            b't' => (self.add_alphabet_term(b'\t'), idx + 1),
+    /// Compile the patterns that follow with `flags` turned on.
+    pub(crate) const fn with_flags(mut self, flags: Flags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Compile `input` into fresh states after the ones already in this NFA. Afterwards
+    /// `start_idx`/`accept_idx` describe the new fragment; earlier fragments are left in place,
+    /// which lets several patterns share one NFA (see `scanner.rs`).
+    pub(crate) const fn add_regex_bytes(mut self, input: &[u8]) -> Self {
+        let syntax = Syntax::from_regex_bytes_with_flags(input, self.flags);
+        self.anchors = syntax.anchors();
+        self.add_syntax(&syntax, input)
+    }
+
+    /// The anchors of the pattern most recently added with `add_regex_bytes`.
+    pub(crate) const fn anchors(&self) -> Anchors {
+        self.anchors
+    }
+
+    pub(crate) const fn start_idx(&self) -> NFASize {
+        self.start_idx
+    }
+
+    pub(crate) const fn accept_idx(&self) -> NFASize {
+        self.accept_idx
+    }
 
-            chara @ (b'$' | b'^' | b'(' | b')' | b'{' | b'}' | b'[' | b']' | b'|' | b'?' | b'*'
-            | b'\\') => (self.add_alphabet_term(chara), idx + 1),
+    /// Every transition, as (from, on, to); `on` is an inclusive range of bytes, or `None` for ε.
+    pub(crate) fn transitions(
+        &self,
+    ) -> impl Iterator<Item = (NFASize, Option<(u8, u8)>, NFASize)> + '_ {
+        self.states[0..self.state_count as usize]
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, state)| {
+                state.transitions[0..state.transition_count as usize]
+                    .iter()
+                    .map(move |transition| {
+                        (idx as NFASize, transition.on_range, transition.to_state_idx)
+                    })
+            })
+    }
 
-            _ => panic!("unexpected escaped character value"),
+    /// Allocate a new state with an ε-transition to each of `targets` and return its index.
+    /// A state only holds `TRANSITIONS_PER_STATE` transitions, so long target lists are spread
+    /// over a chain of states, each linking to the next on ε.
+    pub(crate) fn add_branch(&mut self, targets: &[NFASize]) -> NFASize {
+        let branch_idx = self.state_count;
+        let mut idx = branch_idx;
+        let mut remaining = targets;
+        loop {
+            if self.state_count as usize >= N || self.state_count == NFASize::MAX {
+                panic!("too many NFA states");
+            }
+            self.state_count += 1;
+
+            if remaining.len() <= TRANSITIONS_PER_STATE {
+                for target in remaining {
+                    self.states[idx as usize] =
+                        self.states[idx as usize].add_transition(None, *target);
+                }
+                return branch_idx;
+            }
+
+            let (head, tail) = remaining.split_at(TRANSITIONS_PER_STATE - 1);
+            for target in head {
+                self.states[idx as usize] = self.states[idx as usize].add_transition(None, *target);
+            }
+            self.states[idx as usize] = self.states[idx as usize].add_transition(None, idx + 1);
+            remaining = tail;
+            idx += 1;
+        }
+    }
+
+    // Build a fragment for every node of `syntax`, children first, by the rules below. A symbol
+    // is built whole by rule 2, in place of the positions it was split into; see syntax.rs.
+    const fn add_syntax(mut self, syntax: &Syntax, input: &[u8]) -> Self {
+        // Inline flags only last until the end of the pattern.
+        let outer_flags = self.flags;
+        let nodes = syntax.nodes();
+        let symbols = syntax.symbols();
+        let mut fragments: [(NFASize, NFASize); MAX_NODES] = [(0, 0); MAX_NODES];
+        let mut next_symbol = 0;
+        let mut idx = 0;
+        while idx < nodes.len() {
+            if next_symbol < symbols.len() && symbols[next_symbol].first as usize == idx {
+                let symbol = symbols[next_symbol];
+                let (ranges, count, _) = read_symbol(input, symbol.start, symbol.flags);
+                self.flags = symbol.flags;
+                self = self.add_scalar_ranges_term(&ranges, count);
+                idx = symbol.node as usize;
+                next_symbol += 1;
+            } else {
+                self = match nodes[idx] {
+                    Node::Empty => self.add_empty_term(),
+                    // Every position belongs to a symbol, and those were skipped.
+                    Node::Position(_) => panic!("a position outside of any symbol"),
+                    Node::Concat(s, t) => {
+                        let (s_start, s_accept) = fragments[s as usize];
+                        (self.start_idx, self.accept_idx) = fragments[t as usize];
+                        self.product(s_start, s_accept)
+                    }
+                    Node::Alternate(s, t) => {
+                        let (s_start, s_accept) = fragments[s as usize];
+                        (self.start_idx, self.accept_idx) = fragments[t as usize];
+                        self.alternate(s_start, s_accept)
+                    }
+                    Node::Star(s) => {
+                        (self.start_idx, self.accept_idx) = fragments[s as usize];
+                        self.kleene_star()
+                    }
+                    Node::And(..) | Node::Not(..) => {
+                        panic!("\"&\" and \"~\" can only be compiled through derivatives")
+                    }
+                };
+            }
+            fragments[idx] = (self.start_idx, self.accept_idx);
+            idx += 1;
         }
+
+        (self.start_idx, self.accept_idx) = fragments[syntax.root() as usize];
+        self.flags = outer_flags;
+        self
     }
 
     // Rule 1: For ε, construct an NFA where "i" is a new start state and "f" is a new accepting
@@ -240,16 +760,38 @@ impl<const N: usize> Nfa<N> {
     //     start ----> | i | ---> ‖ f ‖
     //                 +---+      +===+
     //
+    // Under (?i), a letter gets a second transition from i to f for its other case. Folding
+    // happens here, so nothing that runs the NFA (or a DFA built from it) has to know about it.
     const fn add_alphabet_term(self, chara: u8) -> Self {
-        self.add_term(Some(chara))
+        if self.flags.contains(Flags::CASE_INSENSITIVE) && chara.is_ascii_alphabetic() {
+            let lower = chara.to_ascii_lowercase();
+            let upper = chara.to_ascii_uppercase();
+            return self.add_ranges_term(&[(lower, lower), (upper, upper)]);
+        }
+
+        self.add_term(Some((chara, chara)))
     }
 
-    const fn add_term(mut self, chara: Option<u8>) -> Self {
+    const fn add_term(mut self, on_range: Option<(u8, u8)>) -> Self {
         // create two states: i and f; link them
         self.start_idx = self.state_count;
         self.accept_idx = self.state_count + 1;
         self.states[self.start_idx as usize] =
-            self.states[self.start_idx as usize].add_transition(chara, self.accept_idx);
+            self.states[self.start_idx as usize].add_transition(on_range, self.accept_idx);
+        self.state_count += 2;
+        self
+    }
+
+    // Like add_term, but with one edge from i to f for each range.
+    const fn add_ranges_term(mut self, ranges: &[(u8, u8)]) -> Self {
+        self.start_idx = self.state_count;
+        self.accept_idx = self.state_count + 1;
+        let mut idx = 0;
+        while idx < ranges.len() {
+            self.states[self.start_idx as usize] = self.states[self.start_idx as usize]
+                .add_transition(Some(ranges[idx]), self.accept_idx);
+            idx += 1;
+        }
         self.state_count += 2;
         self
     }
@@ -270,16 +812,7 @@ impl<const N: usize> Nfa<N> {
     //                         ↑      ↑
     //            self.start_idx      self.accept_idx
     //
-    const fn alternate(mut self, input: &'static [u8], mut idx: usize) -> (Self, usize) {
-        let prev_start_idx = self.start_idx;
-        let prev_accept_idx = self.accept_idx;
-
-        self.start_idx = self.state_count;
-        self.accept_idx = self.state_count;
-        self.state_count += 2;
-
-        (self, idx) = self.expr(input, idx + 1);
-
+    const fn alternate(mut self, prev_start_idx: NFASize, prev_accept_idx: NFASize) -> Self {
         // 1. alloc two new states: i & f
         // 2. add two transitions from i on ε; one to prev_start_idx, and one to start_idx
         // 3. add transition from prev_accept to f
@@ -303,7 +836,7 @@ impl<const N: usize> Nfa<N> {
         self.start_idx = i_idx;
         self.accept_idx = f_idx;
 
-        (self, idx)
+        self
     }
 
     // 3.b: for the regular expression "st", construct an NFA:
@@ -366,74 +899,131 @@ impl<const N: usize> Nfa<N> {
         self
     }
 
-    // Rule 3.d: for the regular expression (s), construct NFA(s), consuming the left and right parens.
-    const fn group(mut self, input: &'static [u8], mut idx: usize) -> (Self, usize) {
-        if input[idx] != b'(' {
-            panic!("expected '('");
-        }
+    pub(crate) fn debug_print(&self, prefix: &'static [u8]) {
+        self.draw(prefix, &StateSet::new());
+    }
 
-        let last_start_idx = self.start_idx;
-        let last_accept_idx = self.accept_idx;
-        (self, idx) = self.expr(input, idx + 1);
+    // The cluster `debug_print` draws, with the states in `active` filled in.
+    fn draw(&self, prefix: &'static [u8], active: &StateSet) {
+        use crate::dot;
 
-        if idx >= input.len() {
-            panic!("unexpected end of stream: unterminated group, expected ')'");
+        // Only draw states that take part in the automaton: the start and accepting states, and
+        // anything at either end of a transition.
+        let mut drawn = StateSet::new();
+        drawn.insert(self.start_idx);
+        drawn.insert(self.accept_idx);
+        for (idx, state) in self.states[0..self.state_count as usize].iter().enumerate() {
+            for transition in &state.transitions[0..state.transition_count as usize] {
+                drawn.insert(idx as NFASize);
+                drawn.insert(transition.to_state_idx);
+            }
         }
 
-        if b')' == input[idx] {
-            (self, idx) = self.postfix(input, idx + 1);
-            return (self.product(last_start_idx, last_accept_idx), idx);
+        dot::begin_cluster(prefix);
+        for idx in drawn.iter() {
+            let attrs = match (idx == self.accept_idx, active.contains(idx)) {
+                (false, false) => "shape=circle",
+                (true, false) => "shape=doublecircle",
+                (false, true) => "shape=circle, style=filled, fillcolor=\"lightblue\"",
+                (true, true) => "shape=doublecircle, style=filled, fillcolor=\"lightblue\"",
+            };
+            dot::node(prefix, idx as u32, attrs, |label| {
+                label.text("S").number(idx as u32);
+            });
         }
+        dot::start_arrow(prefix, self.start_idx as u32);
 
-        panic!("unterminated group, expected ')'");
+        for (idx, state) in self.states[0..self.state_count as usize].iter().enumerate() {
+            for transition in &state.transitions[0..state.transition_count as usize] {
+                dot::edge(
+                    prefix,
+                    idx as u32,
+                    transition.to_state_idx as u32,
+                    |label| {
+                        match transition.on_range {
+                            Some((lo, hi)) if lo == hi => label.byte(lo),
+                            Some((lo, hi)) => label.byte(lo).text("-").byte(hi),
+                            None => label.epsilon(),
+                        };
+                    },
+                );
+            }
+        }
+        dot::end_cluster();
     }
+}
 
+impl<const N: usize, const C: usize> Dfa<N, C> {
     pub(crate) fn debug_print(&self, prefix: &'static [u8]) {
-        use crate::io::{itoa, puts};
-
-        puts("subgraph ");
-        puts(prefix);
-        puts(" {\n");
-        puts("  label = \"");
-        puts(prefix);
-        puts("\";\n");
-        puts("  rankdir=\"LR\";\n");
-        puts("  ");
-        puts(prefix);
-        puts(itoa(self.start_idx as u32));
-        puts(" [shape=box];\n");
-        puts("  ");
-        puts(prefix);
-        puts(itoa(self.accept_idx as u32));
-        puts(" [shape=doublecircle];\n");
-        for (idx, state) in self.states[0..self.state_count as usize].iter().enumerate() {
-            for transition in &state.transitions[0..state.transition_count as usize] {
-                puts("  ");
-                puts(prefix);
-                puts(itoa(transition.to_state_idx as u32));
-                puts("[label=\"S");
-                puts(itoa(transition.to_state_idx as u32));
-                puts("\"];\n");
-                puts("  ");
-                puts(prefix);
-                puts(itoa(idx as u32));
-                puts(" -> ");
-                puts(prefix);
-                puts(itoa(transition.to_state_idx as u32));
-
-                puts("[label=\"");
-                if let Some(byte) = transition.on_character {
-                    puts([byte]);
-                } else {
-                    puts([0xce, 0xb5]); // epsilon
+        use crate::dot;
+
+        dot::begin_cluster(prefix);
+
+        // The dead state is left out; every edge that isn't drawn goes there.
+        for idx in 1..self.state_count {
+            let attrs = if self.is_accepting(idx) {
+                "shape=doublecircle"
+            } else {
+                "shape=circle"
+            };
+            dot::node(prefix, idx as u32, attrs, |label| {
+                label.text("D").number(idx as u32);
+                // A DFA that didn't come from an NFA (say, a product) has no sets to show.
+                let nfa_states = &self.nfa_states[idx as usize];
+                if nfa_states.is_empty() {
+                    return;
                 }
-                puts("\"];\n");
+                label.text(" {");
+                for (nth, nfa_idx) in nfa_states.iter().enumerate() {
+                    if nth > 0 {
+                        label.text(",");
+                    }
+                    label.number(nfa_idx as u32);
+                }
+                label.text("}");
+            });
+        }
+        dot::start_arrow(prefix, self.start_idx as u32);
+
+        for idx in 1..self.state_count {
+            let mut seen = StateSet::new();
+            for (_, _, target) in self.byte_runs(idx) {
+                if seen.contains(target) {
+                    continue;
+                }
+                seen.insert(target);
+
+                dot::edge(prefix, idx as u32, target as u32, |label| {
+                    let runs = self.byte_runs(idx).filter(|(_, _, to)| *to == target);
+                    for (nth, (lo, hi, _)) in runs.enumerate() {
+                        if nth > 0 {
+                            label.text(",");
+                        }
+                        label.byte(lo);
+                        if hi != lo {
+                            label.text("-").byte(hi);
+                        }
+                    }
+                });
             }
         }
-        puts("}\n");
+        dot::end_cluster();
     }
 }
 
+/// Print an NFA and its DFA next to each other, as two clusters of one digraph.
+pub(crate) fn debug_print_side_by_side<const N: usize, const M: usize, const C: usize>(
+    nfa: &Nfa<N>,
+    dfa: &Dfa<M, C>,
+) {
+    use crate::dot;
+
+    dot::begin_digraph("LR");
+    nfa.debug_print(b"nfa");
+    dfa.debug_print(b"dfa");
+    dot::end_digraph();
+}
+
 #[allow(dead_code)]
 fn dbgnfa<const N: usize>(prefix: &[u8], nfa: &Nfa<N>) {
     use crate::io::{eputs, flush, itoa};
@@ -461,8 +1051,12 @@ fn dbgnfa<const N: usize>(prefix: &[u8], nfa: &Nfa<N>) {
         eputs(itoa(idx as u32));
         eputs(": {");
         for transition in &state.transitions[0..state.transition_count as usize] {
-            if let Some(xs) = transition.on_character {
-                eputs([xs]);
+            if let Some((lo, hi)) = transition.on_range {
+                eputs([lo]);
+                if hi != lo {
+                    eputs("-");
+                    eputs([hi]);
+                }
             } else {
                 eputs("ε");
             }
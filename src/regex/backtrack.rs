@@ -0,0 +1,81 @@
+use super::{NFASize, Nfa, StateSet};
+
+/// A matcher that tries the NFA's paths one at a time, depth first, backing up to the last
+/// choice whenever a path fails: the way Perl-style engines work. It is simple enough to trust,
+/// which makes it a reference to check the other engines against, but it may try exponentially
+/// many paths; `(a|a)*b` against a run of a's tries them all. So it counts its steps, one per
+/// NFA state it enters, and gives up once it has taken `budget` of them.
+pub(crate) struct Backtracker<'a, const N: usize> {
+    nfa: &'a Nfa<N>,
+    budget: usize,
+    steps: usize,
+}
+
+/// The backtracker took its whole budget of steps without an answer.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BudgetExceeded;
+
+impl<'a, const N: usize> Backtracker<'a, N> {
+    pub(crate) fn new(nfa: &'a Nfa<N>, budget: usize) -> Self {
+        Self {
+            nfa,
+            budget,
+            steps: 0,
+        }
+    }
+
+    /// The steps taken by the last call to `matches`.
+    pub(crate) fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Whether the whole of `input` matches, like `Dfa::matches`.
+    pub(crate) fn matches(&mut self, input: &[u8]) -> Result<bool, BudgetExceeded> {
+        self.steps = 0;
+        let mut looped = StateSet::new();
+        looped.insert(self.nfa.start_idx);
+        self.visit(input, self.nfa.start_idx, 0, looped)
+    }
+
+    // Try every path from `state` with `input[idx..]` left to read. `looped` holds the states
+    // entered since the last byte was read: entering one again would go round an ε-cycle, and
+    // whatever lies beyond it is reachable without the cycle, so that path is cut there.
+    fn visit(
+        &mut self,
+        input: &[u8],
+        state: NFASize,
+        idx: usize,
+        looped: StateSet,
+    ) -> Result<bool, BudgetExceeded> {
+        self.steps += 1;
+        if self.steps > self.budget {
+            return Err(BudgetExceeded);
+        }
+        if state == self.nfa.accept_idx && idx == input.len() {
+            return Ok(true);
+        }
+
+        let node = &self.nfa.states[state as usize];
+        for transition in &node.transitions[0..node.transition_count as usize] {
+            let to = transition.to_state_idx;
+            let found = match transition.on_range {
+                None if looped.contains(to) => false,
+                None => {
+                    let mut looped = looped;
+                    looped.insert(to);
+                    self.visit(input, to, idx, looped)?
+                }
+                Some((lo, hi)) if idx < input.len() && lo <= input[idx] && input[idx] <= hi => {
+                    let mut looped = StateSet::new();
+                    looped.insert(to);
+                    self.visit(input, to, idx + 1, looped)?
+                }
+                Some(_) => false,
+            };
+            if found {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
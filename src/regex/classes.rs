@@ -0,0 +1,97 @@
+use super::{Nfa, StateSet};
+
+/// A partition of the 256 byte values into classes that an automaton can't tell apart.
+///
+/// If no transition in the NFA separates two bytes (every range contains both or neither), then
+/// from any set of NFA states they lead to the same set of states, and the DFA's transitions on
+/// them are identical. We only need one column per class, not one per byte. A pattern like
+/// `[a-z]+@[a-z]+` only has five: the bytes below `@`, `@` itself, the bytes between `@` and
+/// `a`, `a-z`, and the bytes above `z`.
+///
+/// Every class here is a contiguous run of bytes, and classes are numbered in byte order.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct ByteClasses {
+    map: [u8; 256],
+    count: u16,
+}
+
+impl ByteClasses {
+    /// Every byte in one class.
+    pub(crate) const fn new() -> Self {
+        Self {
+            map: [0; 256],
+            count: 1,
+        }
+    }
+
+    /// The coarsest classes that no transition of `nfa` splits: a class ends wherever a range
+    /// begins or ends.
+    pub(crate) fn from_nfa<const N: usize>(nfa: &Nfa<N>) -> Self {
+        let mut starts_class = [false; 256];
+        for (_, on_range, _) in nfa.transitions() {
+            if let Some((lo, hi)) = on_range {
+                starts_class[lo as usize] = true;
+                if hi < u8::MAX {
+                    starts_class[hi as usize + 1] = true;
+                }
+            }
+        }
+        Self::from_boundaries(|chara| starts_class[chara as usize])
+    }
+
+    /// The coarsest classes that split none of `sets`, each a set of bytes (as the positions of
+    /// a syntax tree have): a class ends wherever a set does.
+    pub(crate) fn from_byte_sets(sets: &[StateSet]) -> Self {
+        Self::from_boundaries(|chara| {
+            sets.iter()
+                .any(|set| set.contains(chara) != set.contains(chara - 1))
+        })
+    }
+
+    /// Build classes from a predicate saying whether `chara` starts a new class (that is, whether
+    /// it can be told apart from `chara - 1`). It isn't asked about byte 0.
+    pub(crate) fn from_boundaries(starts_class: impl Fn(u8) -> bool) -> Self {
+        let mut classes = Self::new();
+        let mut class = 0u8;
+        for chara in 1..=255u8 {
+            if starts_class(chara) {
+                class += 1;
+            }
+            classes.map[chara as usize] = class;
+        }
+        classes.count = class as u16 + 1;
+        classes
+    }
+
+    /// The coarsest classes that split everything either `self` or `other` splits.
+    pub(crate) fn refine(&self, other: &Self) -> Self {
+        Self::from_boundaries(|chara| {
+            let prev = chara as usize - 1;
+            self.map[prev] != self.map[chara as usize]
+                || other.map[prev] != other.map[chara as usize]
+        })
+    }
+
+    pub(crate) fn class(&self, chara: u8) -> u8 {
+        self.map[chara as usize]
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.count as usize
+    }
+
+    /// The bytes in `class`, as an inclusive range.
+    pub(crate) fn range(&self, class: u8) -> (u8, u8) {
+        let lo = self
+            .map
+            .iter()
+            .position(|c| *c == class)
+            .expect("no such byte class");
+        let hi = self
+            .map
+            .iter()
+            .rposition(|c| *c == class)
+            .expect("no such byte class");
+        (lo as u8, hi as u8)
+    }
+}
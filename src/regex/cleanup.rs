@@ -0,0 +1,156 @@
+use super::{NFASize, Nfa, StateSet, Transition};
+
+impl<const N: usize> Nfa<N> {
+    /// The same NFA with the states that do nothing taken out.
+    ///
+    /// The constructions leave a good deal behind: `product` empties the start state of its
+    /// right operand and never points anything at it again, `alternation` sets aside two states
+    /// it doesn't use, and most of what remains is ε-transitions from one construction to the
+    /// next. This pass
+    ///
+    ///   - drops the states that can't be reached from the start state, and the ones from which
+    ///     the accepting state can't be reached, with every transition to them;
+    ///   - sends each transition into a state whose only transition is one ε-transition, on
+    ///     to that transition's target instead; and
+    ///   - numbers what's left densely, in the order a breadth-first walk from the start state
+    ///     reaches it, so the start state is 0.
+    ///
+    /// Only the fragment from `start_idx` to `accept_idx` survives, so this is for an NFA built
+    /// from one pattern; a scanner's NFA keeps each rule's fragment at its own indices.
+    pub(crate) fn cleanup(&self) -> Self {
+        let live = self.live_states();
+
+        // forward[s]: where a transition into s may as well go.
+        let mut forward = [0 as NFASize; N];
+        for state in 0..self.state_count {
+            let mut target = state;
+            // A chain that went round more than N times would be an ε-cycle without the
+            // accepting state, and those aren't live.
+            let mut hops = 0;
+            while let Some(next) = self.ε_successor(target) {
+                if !live.contains(next) || hops == N {
+                    break;
+                }
+                target = next;
+                hops += 1;
+            }
+            forward[state as usize] = target;
+        }
+
+        // Number the states a breadth-first walk over the forwarded transitions reaches.
+        const UNNUMBERED: NFASize = NFASize::MAX;
+        let mut number = [UNNUMBERED; N];
+        let mut order = [0 as NFASize; N];
+        let start_idx = forward[self.start_idx as usize];
+        number[start_idx as usize] = 0;
+        order[0] = start_idx;
+        let mut count = 1;
+        let mut visited = if live.contains(start_idx) { 0 } else { count };
+        while visited < count {
+            let state = &self.states[order[visited] as usize];
+            visited += 1;
+            for transition in &state.transitions[0..state.transition_count as usize] {
+                let target = forward[transition.to_state_idx as usize];
+                if live.contains(target) && number[target as usize] == UNNUMBERED {
+                    number[target as usize] = count as NFASize;
+                    order[count] = target;
+                    count += 1;
+                }
+            }
+        }
+        // With nothing live, the language is empty: keep a start state and an accepting state
+        // with no way between them.
+        if number[self.accept_idx as usize] == UNNUMBERED {
+            number[self.accept_idx as usize] = count as NFASize;
+            order[count] = self.accept_idx;
+            count += 1;
+        }
+
+        let mut nfa = Self::new();
+        nfa.state_count = count as NFASize;
+        nfa.start_idx = 0;
+        nfa.accept_idx = number[self.accept_idx as usize];
+        nfa.flags = self.flags;
+        nfa.anchors = self.anchors;
+        for (new_idx, old_idx) in order[0..count].iter().enumerate() {
+            if !live.contains(*old_idx) {
+                continue;
+            }
+            let old = &self.states[*old_idx as usize];
+            let new = &mut nfa.states[new_idx];
+            for transition in &old.transitions[0..old.transition_count as usize] {
+                let target = forward[transition.to_state_idx as usize];
+                if !live.contains(target) {
+                    continue;
+                }
+                let transition = Transition {
+                    on_range: transition.on_range,
+                    to_state_idx: number[target as usize],
+                };
+                // Forwarding can make two transitions the same, and an ε-transition from a
+                // state to itself does nothing.
+                let kept = &new.transitions[0..new.transition_count as usize];
+                let is_self_loop =
+                    transition.on_range.is_none() && transition.to_state_idx == new_idx as NFASize;
+                if is_self_loop
+                    || kept.iter().any(|other| {
+                        other.on_range == transition.on_range
+                            && other.to_state_idx == transition.to_state_idx
+                    })
+                {
+                    continue;
+                }
+                *new = new.add_transition(transition.on_range, transition.to_state_idx);
+            }
+        }
+        nfa
+    }
+
+    /// How many states have been allocated, whether or not anything uses them.
+    pub(crate) const fn state_count(&self) -> NFASize {
+        self.state_count
+    }
+
+    // The states on some path from the start state to the accepting state.
+    fn live_states(&self) -> StateSet {
+        let mut reachable = StateSet::new();
+        reachable.insert(self.start_idx);
+        let mut can_accept = StateSet::new();
+        can_accept.insert(self.accept_idx);
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (from, _, to) in self.transitions() {
+                if reachable.contains(from) && !reachable.contains(to) {
+                    reachable.insert(to);
+                    changed = true;
+                }
+                if can_accept.contains(to) && !can_accept.contains(from) {
+                    can_accept.insert(from);
+                    changed = true;
+                }
+            }
+        }
+
+        let mut live = StateSet::new();
+        for state in reachable.iter() {
+            if can_accept.contains(state) {
+                live.insert(state);
+            }
+        }
+        live
+    }
+
+    // The target of `state`'s one transition, if that is all it has and it is on ε. The
+    // accepting state is never skipped: reaching it is what matters.
+    fn ε_successor(&self, state: NFASize) -> Option<NFASize> {
+        let transitions = &self.states[state as usize].transitions;
+        match self.states[state as usize].transition_count {
+            1 if transitions[0].on_range.is_none() && state != self.accept_idx => {
+                Some(transitions[0].to_state_idx)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use super::syntax::{ByteSet, Node, Syntax, MAX_NODES};
+use super::{Anchors, ByteClasses, Dfa};
+
+// Brzozowski's derivatives. The derivative of a regex r with respect to a byte a is the regex
+// for what's left of r's matches that start with a, with the a taken off:
+//
+//     d(∅) = ∅      d(ε) = ∅      d(S) = ε if a is in S, else ∅
+//     d(r|s) = d(r) | d(s)
+//     d(rs)  = d(r) s | d(s)   if r matches ε
+//     d(rs)  = d(r) s          otherwise
+//     d(r*)  = d(r) r*
+//     d(r&s) = d(r) & d(s)
+//     d(¬r)  = ¬d(r)
+//
+// A string matches r if taking the derivative by each of its bytes in turn leaves a regex that
+// matches ε. And if each distinct derivative is a DFA state, with d(r) the transition from r on
+// a, that's a DFA for r; no NFA involved.
+//
+// "Distinct" needs care: r, r|r and r|r|r are all different regexes, and the derivatives of
+// a(ab)* would never stop growing if we didn't see that. Brzozowski showed it is enough to
+// treat | as associative, commutative and idempotent. Here every term is built by a smart
+// constructor that puts it in a normal form (an alternation is a sorted, duplicate-free list;
+// ε and ∅ are simplified away where they can be), and terms are hash-consed, so two terms in
+// normal form are equal exactly when their indices are.
+//
+// The last two rules are why "&" and "~" (see `Flags::SET_OPERATORS`) are compiled here: an NFA
+// has no way to say "and" or "not", but a derivative of r&s or ¬r is no harder to take than one
+// of r|s. & gets the same normal form as |.
+
+const MAX_TERMS: usize = 2048;
+
+// How many alternatives one alternation can have.
+const MAX_ALTERNATIVES: usize = 128;
+
+pub(crate) type TermIdx = u16;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Term {
+    /// ∅: matches nothing.
+    Nothing,
+    /// ε: matches only the empty string.
+    Epsilon,
+    /// One byte from the set.
+    Bytes(ByteSet),
+    Concat(TermIdx, TermIdx),
+    /// r|s, where r isn't an alternation and comes before everything in s.
+    Alternate(TermIdx, TermIdx),
+    Star(TermIdx),
+    /// r&s, in the same form as Alternate.
+    And(TermIdx, TermIdx),
+    /// ¬r: every byte string r doesn't match.
+    Not(TermIdx),
+}
+
+/// An arena of regexes in normal form, and the one compiled into it.
+pub(crate) struct Derivatives {
+    terms: [Term; MAX_TERMS],
+    nullable: [bool; MAX_TERMS],
+    term_count: TermIdx,
+    root: TermIdx,
+    anchors: Anchors,
+}
+
+impl Derivatives {
+    pub(crate) const NOTHING: TermIdx = 0;
+    pub(crate) const EPSILON: TermIdx = 1;
+
+    pub(crate) fn from_regex_bytes(input: &'static [u8]) -> Self {
+        let syntax = Syntax::from_regex_bytes(input);
+
+        let mut derivatives = Self {
+            terms: [Term::Nothing; MAX_TERMS],
+            nullable: [false; MAX_TERMS],
+            term_count: 0,
+            root: Self::NOTHING,
+            anchors: syntax.anchors(),
+        };
+        derivatives.intern(Term::Nothing);
+        derivatives.intern(Term::Epsilon);
+        derivatives.root = derivatives.add_syntax(&syntax);
+        derivatives
+    }
+
+    // The term for a syntax tree. "~r" is the text that r doesn't match, (?s).*&¬r, so that like
+    // "." it never matches invalid UTF-8.
+    fn add_syntax(&mut self, syntax: &Syntax) -> TermIdx {
+        let mut text = None;
+
+        // Children come before parents, so each node's children are already terms.
+        let mut term_of = [Self::NOTHING; MAX_NODES];
+        for (idx, node) in syntax.nodes().iter().enumerate() {
+            term_of[idx] = match *node {
+                Node::Empty => Self::EPSILON,
+                Node::Position(p) => self.bytes(syntax.label(p)),
+                Node::Concat(r, s) => self.concat(term_of[r as usize], term_of[s as usize]),
+                Node::Alternate(r, s) => self.alternate(term_of[r as usize], term_of[s as usize]),
+                Node::Star(r) => self.star(term_of[r as usize]),
+                Node::And(r, s) => self.and(term_of[r as usize], term_of[s as usize]),
+                Node::Not(r) => {
+                    let text = *text.get_or_insert_with(|| {
+                        self.add_syntax(&Syntax::from_regex_bytes(b"(?s).*"))
+                    });
+                    let not = self.not(term_of[r as usize]);
+                    self.and(text, not)
+                }
+            };
+        }
+        term_of[syntax.root() as usize]
+    }
+
+    pub(crate) fn term_count(&self) -> TermIdx {
+        self.term_count
+    }
+
+    pub(crate) fn nullable(&self, term: TermIdx) -> bool {
+        self.nullable[term as usize]
+    }
+
+    /// The derivative of `term` with respect to `chara`.
+    pub(crate) fn derivative(&mut self, term: TermIdx, chara: u8) -> TermIdx {
+        match self.terms[term as usize] {
+            Term::Nothing | Term::Epsilon => Self::NOTHING,
+            Term::Bytes(set) if set.contains(chara) => Self::EPSILON,
+            Term::Bytes(_) => Self::NOTHING,
+            Term::Concat(r, s) => {
+                let dr = self.derivative(r, chara);
+                let left = self.concat(dr, s);
+                if self.nullable(r) {
+                    let ds = self.derivative(s, chara);
+                    self.alternate(left, ds)
+                } else {
+                    left
+                }
+            }
+            Term::Alternate(r, s) => {
+                let dr = self.derivative(r, chara);
+                let ds = self.derivative(s, chara);
+                self.alternate(dr, ds)
+            }
+            Term::Star(r) => {
+                let dr = self.derivative(r, chara);
+                self.concat(dr, term)
+            }
+            Term::And(r, s) => {
+                let dr = self.derivative(r, chara);
+                let ds = self.derivative(s, chara);
+                self.and(dr, ds)
+            }
+            Term::Not(r) => {
+                let dr = self.derivative(r, chara);
+                self.not(dr)
+            }
+        }
+    }
+
+    /// Whether the whole of `input` matches, by taking one derivative per byte.
+    pub(crate) fn matches(&mut self, input: &[u8]) -> bool {
+        let mut term = self.root;
+        for chara in input {
+            term = self.derivative(term, *chara);
+            if term == Self::NOTHING {
+                return false;
+            }
+        }
+        self.nullable(term)
+    }
+
+    fn bytes(&mut self, set: ByteSet) -> TermIdx {
+        if set.is_empty() {
+            return Self::NOTHING;
+        }
+        self.intern(Term::Bytes(set))
+    }
+
+    // ∅r = r∅ = ∅, εr = rε = r, and (rs)t = r(st).
+    fn concat(&mut self, r: TermIdx, s: TermIdx) -> TermIdx {
+        if r == Self::NOTHING || s == Self::NOTHING {
+            return Self::NOTHING;
+        }
+        if r == Self::EPSILON {
+            return s;
+        }
+        if s == Self::EPSILON {
+            return r;
+        }
+        if let Term::Concat(r1, r2) = self.terms[r as usize] {
+            let tail = self.concat(r2, s);
+            return self.concat(r1, tail);
+        }
+        self.intern(Term::Concat(r, s))
+    }
+
+    // The alternatives of r and s together, without ∅, sorted and without duplicates. Byte sets
+    // are merged into one: [ab]|[bc] is [abc].
+    fn alternate(&mut self, r: TermIdx, s: TermIdx) -> TermIdx {
+        self.list(r, s, false)
+    }
+
+    // The same for &, except that ∅ makes the whole thing ∅, and byte sets are intersected:
+    // [ab]&[bc] is [b].
+    fn and(&mut self, r: TermIdx, s: TermIdx) -> TermIdx {
+        self.list(r, s, true)
+    }
+
+    fn list(&mut self, r: TermIdx, s: TermIdx, and: bool) -> TermIdx {
+        let mut items = [Self::NOTHING; MAX_ALTERNATIVES];
+        let mut count = 0;
+        let mut bytes = None;
+        for mut term in [r, s] {
+            loop {
+                let (head, rest) = match self.terms[term as usize] {
+                    Term::Alternate(head, rest) if !and => (head, Some(rest)),
+                    Term::And(head, rest) if and => (head, Some(rest)),
+                    _ => (term, None),
+                };
+                match self.terms[head as usize] {
+                    Term::Nothing if and => return Self::NOTHING,
+                    Term::Nothing => {}
+                    Term::Bytes(set) => {
+                        bytes = Some(match bytes {
+                            None => set,
+                            Some(bytes) if and => set.intersection(bytes),
+                            Some(bytes) => set.union(bytes),
+                        })
+                    }
+                    _ => {
+                        if count == MAX_ALTERNATIVES {
+                            panic!("too many alternatives");
+                        }
+                        items[count] = head;
+                        count += 1;
+                    }
+                }
+                match rest {
+                    Some(rest) => term = rest,
+                    None => break,
+                }
+            }
+        }
+        if let Some(set) = bytes {
+            // Only an intersection can come out empty.
+            let term = self.bytes(set);
+            if term == Self::NOTHING {
+                return Self::NOTHING;
+            }
+            if count == MAX_ALTERNATIVES {
+                panic!("too many alternatives");
+            }
+            items[count] = term;
+            count += 1;
+        }
+
+        let items = &mut items[..count];
+        items.sort_unstable();
+        let mut result = Self::NOTHING;
+        for (nth, term) in items.iter().enumerate().rev() {
+            if nth + 1 < count && items[nth + 1] == *term {
+                continue;
+            }
+            result = if result == Self::NOTHING {
+                *term
+            } else if and {
+                self.intern(Term::And(*term, result))
+            } else {
+                self.intern(Term::Alternate(*term, result))
+            };
+        }
+        result
+    }
+
+    // ¬¬r = r.
+    fn not(&mut self, r: TermIdx) -> TermIdx {
+        if let Term::Not(inner) = self.terms[r as usize] {
+            return inner;
+        }
+        self.intern(Term::Not(r))
+    }
+
+    // ∅* = ε* = ε, and r** = r*.
+    fn star(&mut self, r: TermIdx) -> TermIdx {
+        if r == Self::NOTHING || r == Self::EPSILON {
+            return Self::EPSILON;
+        }
+        if let Term::Star(_) = self.terms[r as usize] {
+            return r;
+        }
+        self.intern(Term::Star(r))
+    }
+
+    fn intern(&mut self, term: Term) -> TermIdx {
+        if let Some(idx) = self.terms[..self.term_count as usize]
+            .iter()
+            .position(|existing| *existing == term)
+        {
+            return idx as TermIdx;
+        }
+
+        if self.term_count as usize >= MAX_TERMS {
+            panic!("too many derivative terms");
+        }
+        let idx = self.term_count;
+        self.term_count += 1;
+        self.terms[idx as usize] = term;
+        self.nullable[idx as usize] = match term {
+            Term::Nothing | Term::Bytes(_) => false,
+            Term::Epsilon | Term::Star(_) => true,
+            Term::Concat(r, s) => self.nullable(r) && self.nullable(s),
+            Term::Alternate(r, s) => self.nullable(r) || self.nullable(s),
+            Term::And(r, s) => self.nullable(r) && self.nullable(s),
+            Term::Not(r) => !self.nullable(r),
+        };
+        idx
+    }
+}
+
+impl<const N: usize, const C: usize> Dfa<N, C> {
+    /// A DFA whose states are the distinct derivatives of the pattern: state r goes to d(r) on
+    /// a, and accepts if r matches ε. ∅ is the dead state. The worklist is the same as the
+    /// subset construction's.
+    pub(crate) fn from_derivatives(derivatives: &mut Derivatives) -> Self {
+        let mut dfa = Self::new();
+        dfa.anchors = derivatives.anchors;
+
+        // A derivative never has a byte set with a boundary the pattern's sets don't have (it
+        // only merges them), so these classes hold for every derivative.
+        let arena = &derivatives.terms[..derivatives.term_count as usize];
+        dfa.classes = ByteClasses::from_boundaries(|chara| {
+            arena.iter().any(|term| match term {
+                Term::Bytes(set) => set.contains(chara) != set.contains(chara - 1),
+                _ => false,
+            })
+        });
+        if dfa.classes.count() > C {
+            panic!("too many byte classes");
+        }
+
+        // terms[idx] is the derivative state idx stands for.
+        let mut terms = [Derivatives::NOTHING; N];
+        let intern = |dfa: &mut Self, terms: &mut [TermIdx; N], term: TermIdx, nullable: bool| {
+            if term == Derivatives::NOTHING {
+                return Self::DEAD;
+            }
+            if let Some(idx) = (1..dfa.state_count).find(|idx| terms[*idx as usize] == term) {
+                return idx;
+            }
+            if !dfa.has_room() {
+                panic!("too many DFA states");
+            }
+            let idx = dfa.state_count;
+            dfa.state_count += 1;
+            terms[idx as usize] = term;
+            if nullable {
+                dfa.accepting.insert(idx);
+            }
+            idx
+        };
+
+        let root = derivatives.root;
+        dfa.start_idx = intern(&mut dfa, &mut terms, root, derivatives.nullable(root));
+
+        while dfa.marked_count < dfa.state_count {
+            let t = dfa.marked_count;
+            dfa.marked_count += 1;
+
+            for class in 0..dfa.classes.count() {
+                let (chara, _) = dfa.classes.range(class as u8);
+                let u = derivatives.derivative(terms[t as usize], chara);
+                let u = intern(&mut dfa, &mut terms, u, derivatives.nullable(u));
+                dfa.set_transition(t, class, u);
+            }
+        }
+
+        dfa
+    }
+}
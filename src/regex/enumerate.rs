@@ -0,0 +1,146 @@
+use super::{DFASize, Dfa, StateSet};
+
+/// The longest string `Dfa::enumerate` will build.
+pub(crate) const MAX_WORD_LEN: usize = 64;
+
+/// One string of a DFA's language.
+#[derive(Clone, Copy)]
+pub(crate) struct Word {
+    bytes: [u8; MAX_WORD_LEN],
+    len: usize,
+}
+
+impl Word {
+    pub(super) const fn new() -> Self {
+        Self {
+            bytes: [0; MAX_WORD_LEN],
+            len: 0,
+        }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes[0..self.len]
+    }
+
+    pub(super) fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes[0..self.len]
+    }
+
+    pub(super) fn push(&mut self, byte: u8) {
+        if self.len == MAX_WORD_LEN {
+            panic!("word too long");
+        }
+        self.bytes[self.len] = byte;
+        self.len += 1;
+    }
+}
+
+/// The strings a DFA accepts, shortest first and in byte order within a length.
+///
+/// Each length is a depth-first walk over the bytes, pruned with a table of which states can
+/// still reach an accepting state in exactly the number of bytes left. Every branch the walk
+/// takes ends in a string, so the cost is proportional to the output, however sparse the
+/// language is.
+pub(crate) struct Enumerate<'a, const N: usize, const C: usize> {
+    dfa: &'a Dfa<N, C>,
+    max_len: usize,
+    // can_finish[k] holds the states that accept after exactly k more bytes.
+    can_finish: [StateSet; MAX_WORD_LEN + 1],
+    // The string of length `len` we are at, and the states[i] the DFA is in after i bytes of it.
+    word: Word,
+    states: [DFASize; MAX_WORD_LEN + 1],
+    // Whether `word` has been returned yet; if not, the walk for `len` hasn't started.
+    started: bool,
+}
+
+impl<const N: usize, const C: usize> Dfa<N, C> {
+    /// Every accepted string of at most `max_len` bytes, in shortlex order. Languages are often
+    /// infinite, or just large (`.` alone is over a million strings), so limit the count with
+    /// `take`.
+    pub(crate) fn enumerate(&self, max_len: usize) -> Enumerate<'_, N, C> {
+        if max_len > MAX_WORD_LEN {
+            panic!("can't enumerate strings that long");
+        }
+
+        let mut can_finish = [StateSet::new(); MAX_WORD_LEN + 1];
+        can_finish[0] = self.accepting;
+        for k in 1..=max_len {
+            for state in 1..self.state_count {
+                if self
+                    .row(state)
+                    .iter()
+                    .any(|target| can_finish[k - 1].contains(*target))
+                {
+                    can_finish[k].insert(state);
+                }
+            }
+        }
+
+        let mut states = [Self::DEAD; MAX_WORD_LEN + 1];
+        states[0] = self.start_idx;
+        Enumerate {
+            dfa: self,
+            max_len,
+            can_finish,
+            word: Word::new(),
+            states,
+            started: false,
+        }
+    }
+}
+
+impl<const N: usize, const C: usize> Enumerate<'_, N, C> {
+    // Fill in word[depth..len] with the smallest bytes that still lead to acceptance, starting
+    // from `from` at `depth`. Only the first position can fail: after that, the pruning table
+    // promises a way through.
+    fn descend(&mut self, mut depth: usize, from: u8) -> bool {
+        let len = self.word.len;
+        let mut from = from as usize;
+        while depth < len {
+            let state = self.states[depth];
+            let can_finish = &self.can_finish[len - depth - 1];
+            let Some(chara) = (from..256)
+                .map(|chara| chara as u8)
+                .find(|chara| can_finish.contains(self.dfa.step(state, *chara)))
+            else {
+                return false;
+            };
+
+            self.word.bytes[depth] = chara;
+            self.states[depth + 1] = self.dfa.step(state, chara);
+            depth += 1;
+            from = 0;
+        }
+        true
+    }
+
+    // The next string of the same length: bump the last byte that can be bumped.
+    fn advance(&mut self) -> bool {
+        (0..self.word.len).rev().any(|depth| {
+            let chara = self.word.bytes[depth];
+            chara < u8::MAX && self.descend(depth, chara + 1)
+        })
+    }
+}
+
+impl<const N: usize, const C: usize> Iterator for Enumerate<'_, N, C> {
+    type Item = Word;
+
+    fn next(&mut self) -> Option<Word> {
+        while self.word.len <= self.max_len {
+            let found = if self.started {
+                self.advance()
+            } else {
+                self.started = true;
+                self.can_finish[self.word.len].contains(self.states[0]) && self.descend(0, 0)
+            };
+            if found {
+                return Some(self.word);
+            }
+
+            self.word.len += 1;
+            self.started = false;
+        }
+        None
+    }
+}
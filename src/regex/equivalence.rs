@@ -0,0 +1,142 @@
+use super::{Anchors, DFASize, Dfa, Nfa};
+
+// Two regular expressions are equivalent when their DFAs accept the same strings, which we can
+// decide by running the DFAs side by side: the product construction, accepting where exactly
+// one of them accepts. The patterns are equivalent if no such state can be reached, and a
+// breadth-first search for one finds the shortest string that tells them apart.
+
+// The largest DFA we will build: as many states as a DFASize can count.
+const MAX_STATES: usize = DFASize::MAX as usize;
+
+/// A string that one pattern matches and the other doesn't.
+pub(crate) struct Counterexample {
+    bytes: [u8; MAX_STATES],
+    len: usize,
+    /// Whether the first pattern is the one that matches it.
+    pub(crate) matched_by_a: bool,
+}
+
+impl Counterexample {
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes[0..self.len]
+    }
+}
+
+/// Why two patterns aren't equivalent.
+// Without an allocator there is no boxing the counterexample, and a Difference is never stored.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Difference {
+    /// A string that one of them matches and the other doesn't.
+    Counterexample(Counterexample),
+    /// They match the same strings, but are anchored differently, as "^a" and "a" are: a search
+    /// finds them in different places.
+    Anchors,
+}
+
+/// The DFAs for two patterns, or their product, need more states than `equivalent` has room for.
+pub(crate) struct TooLarge;
+
+/// Whether `a` and `b` are the same pattern in all but spelling: `None` if they are, and if not,
+/// a shortest string that only one of them matches -- where several bytes would do, a printable
+/// one -- or, if there is no such string, that their anchors differ.
+pub(crate) fn equivalent<const N: usize, const M: usize>(
+    a: &Nfa<N>,
+    b: &Nfa<M>,
+) -> Result<Option<Difference>, TooLarge> {
+    equivalent_anchored(
+        Dfa::<MAX_STATES>::try_from_nfa(a).ok_or(TooLarge)?,
+        Dfa::<MAX_STATES>::try_from_nfa(b).ok_or(TooLarge)?,
+    )
+}
+
+/// `equivalent`, for two DFAs that are already built, as they must be for patterns with "&" or
+/// "~" under (?b). Unlike `equivalent_dfas`, their anchors may differ.
+pub(crate) fn equivalent_anchored<
+    const N: usize,
+    const CN: usize,
+    const M: usize,
+    const CM: usize,
+>(
+    mut a: Dfa<N, CN>,
+    mut b: Dfa<M, CM>,
+) -> Result<Option<Difference>, TooLarge> {
+    let anchors_differ = a.anchors != b.anchors;
+
+    // `Dfa::matches` looks at whole strings, where "^" and "$" make no difference.
+    a.anchors = Anchors::NONE;
+    b.anchors = Anchors::NONE;
+    Ok(match equivalent_dfas(&a, &b)? {
+        Some(counterexample) => Some(Difference::Counterexample(counterexample)),
+        None if anchors_differ => Some(Difference::Anchors),
+        None => None,
+    })
+}
+
+/// `equivalent`, for two DFAs that are already built. Their anchors must be the same.
+pub(crate) fn equivalent_dfas<const N: usize, const CN: usize, const M: usize, const CM: usize>(
+    a: &Dfa<N, CN>,
+    b: &Dfa<M, CM>,
+) -> Result<Option<Counterexample>, TooLarge> {
+    let either = Dfa::<MAX_STATES>::try_product(a, b, |in_a, in_b| in_a != in_b).ok_or(TooLarge)?;
+
+    Ok(either
+        .shortest_accepted()
+        .map(|(bytes, len)| Counterexample {
+            bytes,
+            len,
+            matched_by_a: a.matches(&bytes[0..len]),
+        }))
+}
+
+impl<const N: usize, const C: usize> Dfa<N, C> {
+    // Breadth-first search from the start state for an accepting state. A shortest path visits
+    // no state twice, so it is shorter than the number of states.
+    fn shortest_accepted(&self) -> Option<([u8; MAX_STATES], usize)> {
+        // How each state was first reached: from which state, on which byte.
+        let mut parent = [(Self::DEAD, 0u8); N];
+        let mut queue = [Self::DEAD; N];
+        let mut visited = [false; N];
+        let (mut head, mut tail) = (0, 0);
+
+        queue[tail] = self.start_idx;
+        tail += 1;
+        visited[self.start_idx as usize] = true;
+        while head < tail {
+            let state = queue[head];
+            head += 1;
+
+            if self.is_accepting(state) {
+                let mut bytes = [0u8; MAX_STATES];
+                let mut len = 0;
+                let mut at = state;
+                while at != self.start_idx {
+                    let (from, chara) = parent[at as usize];
+                    bytes[len] = chara;
+                    len += 1;
+                    at = from;
+                }
+                bytes[0..len].reverse();
+                return Some((bytes, len));
+            }
+
+            for class in 0..self.classes.count() {
+                let target = self.transition(state, class);
+                if target == Self::DEAD || visited[target as usize] {
+                    continue;
+                }
+                visited[target as usize] = true;
+                parent[target as usize] = (state, self.example_byte(class as u8));
+                queue[tail] = target;
+                tail += 1;
+            }
+        }
+
+        None
+    }
+
+    // A byte from `class` to put in an example string: printable ASCII if there is any.
+    fn example_byte(&self, class: u8) -> u8 {
+        let (lo, hi) = self.classes.range(class);
+        (lo..=hi).find(u8::is_ascii_graphic).unwrap_or(lo)
+    }
+}
@@ -0,0 +1,61 @@
+use super::syntax::Syntax;
+use super::{ByteClasses, Dfa, StateSet};
+
+// The dragon book's other route from a regular expression to a DFA (section 3.9.5), which never
+// builds an NFA. Augment the regex r to (r)#, where the endmarker # is a position that no byte
+// matches; work out firstpos, lastpos and followpos over the syntax tree (syntax.rs calls them
+// first, last and follow); and let each DFA state be a set of positions: the ones that could
+// match the next byte. A state accepts if # is one of them.
+//
+// It is the subset construction run on the Glushkov NFA, give or take the start state, but it
+// shares no code with `Nfa` or the subset construction in regex.rs. That makes it a check on
+// both: a DFA built this way and one built from Thompson's NFA must match the same strings.
+
+impl<const N: usize, const C: usize> Dfa<N, C> {
+    /// Compile `input` to a DFA by way of followpos rather than an NFA. `nfa_states` holds the
+    /// set of positions each state stands for.
+    ///
+    ///     initialize Dstates to contain only the unmarked state firstpos(n0),
+    ///     where n0 is the root of the syntax tree for (r)#
+    ///     while there is an unmarked state S in Dstates; do
+    ///       mark S
+    ///       for each input symbol a; do
+    ///         let U be the union of followpos(p) for all p in S that correspond to a
+    ///         if U is not in Dstates; do
+    ///           add U as an unmarked state to Dstates
+    ///         done
+    ///         Dtran[S, a] := U
+    ///       done
+    ///     done
+    pub(crate) fn from_regex_bytes_direct(input: &'static [u8]) -> Self {
+        let (syntax, end_marker) = Syntax::from_regex_bytes(input).augment();
+        let positions = syntax.positions();
+
+        let mut dfa = Self::new();
+        dfa.classes = ByteClasses::from_byte_sets(&positions.labels[1..=positions.count as usize]);
+        if dfa.classes.count() > C {
+            panic!("too many byte classes");
+        }
+        dfa.anchors = positions.anchors;
+        dfa.start_idx = dfa.intern(positions.first, positions.first.contains(end_marker));
+
+        while dfa.marked_count < dfa.state_count {
+            let s = dfa.marked_count;
+            dfa.marked_count += 1;
+
+            for class in 0..dfa.classes.count() {
+                let (chara, _) = dfa.classes.range(class as u8);
+                let mut u = StateSet::new();
+                for p in dfa.nfa_states[s as usize].iter() {
+                    if positions.labels[p as usize].contains(chara) {
+                        u = u.union(positions.follow[p as usize]);
+                    }
+                }
+                let u = dfa.intern(u, u.contains(end_marker));
+                dfa.set_transition(s, class, u);
+            }
+        }
+
+        dfa
+    }
+}
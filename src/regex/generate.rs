@@ -0,0 +1,188 @@
+use super::enumerate::Word;
+use super::{r#move, ε_closure, DFASize, Dfa, Nfa, StateSet, MAX_WORD_LEN};
+use crate::random::Rng;
+
+// Random strings for fuzzing. A random walk from the start state finds strings in the language:
+// at each step it picks uniformly between stopping, if it is in an accepting state, and each
+// way forward that can still reach an accepting state within the bytes left, then a random byte
+// that takes that way. Picking ways rather than bytes means a one-byte branch is as likely as a
+// 200-byte range, so every edge gets exercised. The "bytes left" check uses each state's
+// distance to acceptance, so the walk never strands itself, however long or short the limit.
+//
+// Strings just outside the language are found by changing one byte of a string inside it.
+
+// The distance of a state that can't reach acceptance at all.
+const UNREACHABLE: usize = usize::MAX;
+
+// How many mutated strings to try before deciding there may be no near miss to find.
+const NEAR_MISS_TRIES: usize = 32;
+
+impl<const N: usize, const C: usize> Dfa<N, C> {
+    /// A random accepted string of at most `max_len` bytes, or `None` if there is none that
+    /// short.
+    pub(crate) fn generate(&self, rng: &mut Rng, max_len: usize) -> Option<Word> {
+        if max_len > MAX_WORD_LEN {
+            panic!("can't generate strings that long");
+        }
+
+        let distance = self.distances();
+        let mut state = self.start_idx;
+        if distance[state as usize] > max_len {
+            return None;
+        }
+
+        let mut word = Word::new();
+        loop {
+            let left = max_len - word.as_bytes().len();
+            let row = self.row(state);
+            let fits = |class: &usize| distance[row[*class] as usize] < left;
+
+            let stop = self.is_accepting(state) as usize;
+            let pick = rng.below(stop + (0..row.len()).filter(fits).count());
+            if pick < stop {
+                return Some(word);
+            }
+
+            let class = (0..row.len()).filter(fits).nth(pick - stop).unwrap();
+            let (lo, hi) = self.classes.range(class as u8);
+            word.push(rng.byte_between(lo, hi));
+            state = row[class];
+        }
+    }
+
+    /// A random string the DFA rejects but that is one byte away from one it accepts. `None` if
+    /// a few tries turn up nothing, as happens when nearly every string is accepted.
+    pub(crate) fn generate_near_miss(&self, rng: &mut Rng, max_len: usize) -> Option<Word> {
+        near_miss(
+            rng,
+            |rng| self.generate(rng, max_len),
+            |input| self.matches(input),
+        )
+    }
+
+    // The fewest bytes that take each state to an accepting state.
+    fn distances(&self) -> [usize; N] {
+        let mut distance = [UNREACHABLE; N];
+        for state in self.accepting.iter() {
+            distance[state as usize] = 0;
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for state in 1..self.state_count as usize {
+                for target in self.row(state as DFASize) {
+                    let through = distance[*target as usize].saturating_add(1);
+                    if through < distance[state] {
+                        distance[state] = through;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        distance
+    }
+}
+
+impl<const N: usize> Nfa<N> {
+    /// A random string the NFA matches, of at most `max_len` bytes, or `None` if there is none
+    /// that short. This walks sets of states, as the subset construction would, so ε-cycles
+    /// can't trap it.
+    pub(crate) fn generate(&self, rng: &mut Rng, max_len: usize) -> Option<Word> {
+        if max_len > MAX_WORD_LEN {
+            panic!("can't generate strings that long");
+        }
+
+        let distance = self.distances();
+        let mut start = StateSet::new();
+        start.insert(self.start_idx);
+        let mut set = ε_closure(self, start);
+        if set.iter().all(|state| distance[state as usize] > max_len) {
+            return None;
+        }
+
+        let mut word = Word::new();
+        loop {
+            let left = max_len - word.as_bytes().len();
+            let fits = |(from, on, to): &(u8, Option<(u8, u8)>, u8)| {
+                on.is_some() && set.contains(*from) && distance[*to as usize] < left
+            };
+
+            let stop = set.contains(self.accept_idx) as usize;
+            let pick = rng.below(stop + self.transitions().filter(fits).count());
+            if pick < stop {
+                return Some(word);
+            }
+
+            let (_, on, _) = self.transitions().filter(fits).nth(pick - stop).unwrap();
+            let (lo, hi) = on.unwrap();
+            let chara = rng.byte_between(lo, hi);
+            word.push(chara);
+            set = ε_closure(self, r#move(self, &set, chara));
+        }
+    }
+
+    /// A random string the NFA doesn't match but that is one byte away from one it does. `None`
+    /// if a few tries turn up nothing, as happens when nearly every string is matched.
+    pub(crate) fn generate_near_miss(&self, rng: &mut Rng, max_len: usize) -> Option<Word> {
+        near_miss(
+            rng,
+            |rng| self.generate(rng, max_len),
+            |input| self.accepts(input),
+        )
+    }
+
+    // Whether the whole of `input` takes the NFA from its start to its accepting state.
+    fn accepts(&self, input: &[u8]) -> bool {
+        let mut set = StateSet::new();
+        set.insert(self.start_idx);
+        set = ε_closure(self, set);
+        for chara in input {
+            set = ε_closure(self, r#move(self, &set, *chara));
+        }
+        set.contains(self.accept_idx)
+    }
+
+    // The fewest bytes that take each state to the accepting state; ε-transitions cost nothing.
+    fn distances(&self) -> [usize; N] {
+        let mut distance = [UNREACHABLE; N];
+        distance[self.accept_idx as usize] = 0;
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (from, on, to) in self.transitions() {
+                let through = distance[to as usize].saturating_add(on.is_some() as usize);
+                if through < distance[from as usize] {
+                    distance[from as usize] = through;
+                    changed = true;
+                }
+            }
+        }
+        distance
+    }
+}
+
+// Change one random byte of a random accepted string to a different random byte, until the
+// result is rejected. The empty string has no byte to change, so it gets one added instead.
+fn near_miss(
+    rng: &mut Rng,
+    generate: impl Fn(&mut Rng) -> Option<Word>,
+    accepts: impl Fn(&[u8]) -> bool,
+) -> Option<Word> {
+    for _ in 0..NEAR_MISS_TRIES {
+        let mut word = generate(rng)?;
+        let bytes = word.as_bytes_mut();
+        if bytes.is_empty() {
+            word.push(rng.byte_between(0, u8::MAX));
+        } else {
+            let idx = rng.below(bytes.len());
+            bytes[idx] ^= rng.byte_between(1, u8::MAX);
+        }
+
+        if !accepts(word.as_bytes()) {
+            return Some(word);
+        }
+    }
+    None
+}
@@ -0,0 +1,266 @@
+use super::{utf8, Anchors, Nfa, MAX_CLASS_RANGES};
+
+// Shell-style globs, compiled into the same Thompson NFA as regular expressions, with the same
+// constructions (`product`, `alternate`, `kleene_star`), so that everything that runs a regex
+// runs a glob too. A glob matches a whole path:
+//
+//     *        any run of characters but "/"        [^/]*
+//     ?        any one character but "/"            [^/]
+//     **/      any run of directories, or none      ([^/]*/)*
+//     **       any run of characters at all         (?s).*
+//     [a-z]    one character in the class, not "/"
+//     [!a-z]   one character not in it (or [^a-z]), not "/"
+//     {a,b*}   either of the globs inside           (a|b[^/]*)
+//     \c       the character c itself
+//
+// In a class, a "]" right after the "[" (or "[!") is just a "]".
+
+const SLASH: u32 = b'/' as u32;
+
+// Every character but "/", which only "**" ever matches.
+const NOT_SLASH: [(u32, u32); 2] = [(0, SLASH - 1), (SLASH + 1, utf8::MAX_SCALAR)];
+
+// How many ranges a class can come to once "/" is taken out of it.
+const MAX_GLOB_RANGES: usize = 2 * MAX_CLASS_RANGES;
+
+impl<const N: usize> Nfa<N> {
+    pub(crate) const fn from_glob_bytes(input: &'static [u8]) -> Self {
+        Self::new().add_glob_bytes(input)
+    }
+
+    /// Compile the glob `input` into fresh states after the ones already in this NFA, like
+    /// `add_regex_bytes`. Only `Flags::CASE_INSENSITIVE` affects a glob.
+    pub(crate) const fn add_glob_bytes(mut self, input: &'static [u8]) -> Self {
+        self.anchors = Anchors::NONE;
+        (self, _) = self.glob_sequence(input, 0, false);
+        self
+    }
+
+    /* Language:
+     *
+     *     glob     -> piece*
+     *     piece    -> "*" | "?" | "**" "/"?
+     *               | "[" ("!" | "^")? class "]"
+     *               | "{" glob ("," glob)* "}"
+     *               | "\\" <any>
+     *               | 𝛼 in Σ
+     *     class    -> item ("-" item)? class
+     *               | item ("-" item)?
+     *     item     -> "\\" <any>
+     *               | 𝛼 in Σ
+     *
+     * Inside braces, an unescaped "," or "}" ends a glob; elsewhere they are plain characters.
+     */
+    const fn glob_sequence(
+        mut self,
+        input: &'static [u8],
+        mut idx: usize,
+        in_braces: bool,
+    ) -> (Self, usize) {
+        let mut empty = true;
+        while idx < input.len() && !(in_braces && (input[idx] == b',' || input[idx] == b'}')) {
+            let last_start_idx = self.start_idx;
+            let last_accept_idx = self.accept_idx;
+            (self, idx) = self.glob_piece(input, idx);
+            if !empty {
+                self = self.product(last_start_idx, last_accept_idx);
+            }
+            empty = false;
+        }
+
+        if empty {
+            self = self.add_empty_term();
+        }
+        (self, idx)
+    }
+
+    const fn glob_piece(mut self, input: &'static [u8], idx: usize) -> (Self, usize) {
+        match input[idx] {
+            b'*' if idx + 2 < input.len() && input[idx + 1] == b'*' && input[idx + 2] == b'/' => {
+                self = self.add_scalar_ranges_term(&NOT_SLASH, 2).kleene_star();
+                let (last_start_idx, last_accept_idx) = (self.start_idx, self.accept_idx);
+                self = self
+                    .add_alphabet_term(b'/')
+                    .product(last_start_idx, last_accept_idx)
+                    .kleene_star();
+                (self, idx + 3)
+            }
+            b'*' if idx + 1 < input.len() && input[idx + 1] == b'*' => (
+                self.add_scalar_ranges_term(&[(0, utf8::MAX_SCALAR)], 1)
+                    .kleene_star(),
+                idx + 2,
+            ),
+            b'*' => (
+                self.add_scalar_ranges_term(&NOT_SLASH, 2).kleene_star(),
+                idx + 1,
+            ),
+            b'?' => (self.add_scalar_ranges_term(&NOT_SLASH, 2), idx + 1),
+            b'[' => self.glob_class(input, idx + 1),
+            b'{' => self.glob_braces(input, idx + 1),
+            b'\\' => {
+                if idx + 1 >= input.len() {
+                    panic!("unexpected end of input: expected escaped character");
+                }
+                self.add_glob_character(input, idx + 1)
+            }
+            _ => self.add_glob_character(input, idx),
+        }
+    }
+
+    // The alternatives of "{...}", joined with `alternate`. `idx` is just past the "{".
+    const fn glob_braces(mut self, input: &'static [u8], mut idx: usize) -> (Self, usize) {
+        (self, idx) = self.glob_sequence(input, idx, true);
+        while idx < input.len() && input[idx] == b',' {
+            let last_start_idx = self.start_idx;
+            let last_accept_idx = self.accept_idx;
+            (self, idx) = self.glob_sequence(input, idx + 1, true);
+            self = self.alternate(last_start_idx, last_accept_idx);
+        }
+
+        if idx >= input.len() {
+            panic!("unexpected end of input: unterminated braces, expected '}}'");
+        }
+        (self, idx + 1)
+    }
+
+    // A bracketed class, less "/". `idx` is just past the "[".
+    const fn glob_class(self, input: &'static [u8], mut idx: usize) -> (Self, usize) {
+        let mut ranges = [(0u32, 0u32); MAX_CLASS_RANGES];
+        let mut count = 0;
+
+        let negated = idx < input.len() && (input[idx] == b'!' || input[idx] == b'^');
+        if negated {
+            idx += 1;
+        }
+
+        let first_idx = idx;
+        loop {
+            if idx >= input.len() {
+                panic!("unexpected end of input: unterminated class, expected ']'");
+            }
+            if input[idx] == b']' && idx != first_idx {
+                break;
+            }
+
+            let lo;
+            (lo, idx) = Self::glob_class_item(input, idx);
+            let mut hi = lo;
+            // A "-" right before the "]" is just a "-".
+            if idx + 1 < input.len() && input[idx] == b'-' && input[idx + 1] != b']' {
+                (hi, idx) = Self::glob_class_item(input, idx + 1);
+                if hi < lo {
+                    panic!("class range out of order");
+                }
+            }
+
+            if count == MAX_CLASS_RANGES {
+                panic!("too many ranges in class");
+            }
+            ranges[count] = (lo, hi);
+            count += 1;
+        }
+
+        let (ranges, count) = if negated {
+            complement(&ranges, count)
+        } else {
+            without_slash(&ranges, count)
+        };
+        (self.add_scalar_ranges_term(&ranges, count), idx + 1)
+    }
+
+    // One character of a class, as a scalar value, and the index after it. A backslash escapes
+    // any character.
+    const fn glob_class_item(input: &'static [u8], idx: usize) -> (u32, usize) {
+        if input[idx] != b'\\' {
+            return utf8::decode(input, idx);
+        }
+        if idx + 1 >= input.len() {
+            panic!("unexpected end of input: expected escaped character");
+        }
+        utf8::decode(input, idx + 1)
+    }
+
+    // The character starting at `input[idx]`, matched literally.
+    const fn add_glob_character(self, input: &'static [u8], idx: usize) -> (Self, usize) {
+        if input[idx] < 0x80 {
+            return (self.add_alphabet_term(input[idx]), idx + 1);
+        }
+        let (scalar, end_idx) = utf8::decode(input, idx);
+        (self.add_scalar_ranges_term(&[(scalar, scalar)], 1), end_idx)
+    }
+}
+
+// `ranges[0..count]`, with "/" taken out.
+const fn without_slash(
+    ranges: &[(u32, u32)],
+    count: usize,
+) -> ([(u32, u32); MAX_GLOB_RANGES], usize) {
+    let mut out = [(0u32, 0u32); MAX_GLOB_RANGES];
+    let mut out_count = 0;
+    let mut idx = 0;
+    while idx < count {
+        (out, out_count) = push_without_slash(out, out_count, ranges[idx].0, ranges[idx].1);
+        idx += 1;
+    }
+    (out, out_count)
+}
+
+// Every scalar value that is in none of `ranges[0..count]` and isn't "/". The ranges may be in
+// any order and may overlap, so walk up from 0, hopping over whatever range covers the next
+// value and emitting the gaps in between.
+const fn complement(ranges: &[(u32, u32)], count: usize) -> ([(u32, u32); MAX_GLOB_RANGES], usize) {
+    let mut out = [(0u32, 0u32); MAX_GLOB_RANGES];
+    let mut out_count = 0;
+    let mut next = 0;
+    loop {
+        let mut covered = true;
+        while covered {
+            covered = false;
+            let mut idx = 0;
+            while idx < count {
+                let (lo, hi) = ranges[idx];
+                if lo <= next && next <= hi {
+                    if hi == utf8::MAX_SCALAR {
+                        return (out, out_count);
+                    }
+                    next = hi + 1;
+                    covered = true;
+                }
+                idx += 1;
+            }
+        }
+
+        let mut end = utf8::MAX_SCALAR;
+        let mut idx = 0;
+        while idx < count {
+            let lo = ranges[idx].0;
+            if lo > next && lo - 1 < end {
+                end = lo - 1;
+            }
+            idx += 1;
+        }
+
+        (out, out_count) = push_without_slash(out, out_count, next, end);
+        if end == utf8::MAX_SCALAR {
+            return (out, out_count);
+        }
+        next = end + 1;
+    }
+}
+
+const fn push_without_slash(
+    mut out: [(u32, u32); MAX_GLOB_RANGES],
+    mut count: usize,
+    lo: u32,
+    hi: u32,
+) -> ([(u32, u32); MAX_GLOB_RANGES], usize) {
+    if lo < SLASH {
+        out[count] = (lo, if hi < SLASH { hi } else { SLASH - 1 });
+        count += 1;
+    }
+    if hi > SLASH {
+        out[count] = (if lo > SLASH { lo } else { SLASH + 1 }, hi);
+        count += 1;
+    }
+    (out, count)
+}
@@ -0,0 +1,101 @@
+use super::syntax::{ByteSet, Syntax};
+use super::{Anchors, ByteClasses, Dfa, NFASize, StateSet};
+
+/// # Glushkov NFA
+///
+/// The other classic route from a regex to an NFA. Where Thompson's construction glues
+/// fragments together with ε-transitions, Glushkov's has none: there is one state per position
+/// of the pattern (see syntax.rs), plus a state 0 to start in, and being in state p means "the
+/// last byte read was matched by position p". So:
+///
+///     from 0, read a byte in label(q) and go to q,  for each q in first
+///     from p, read a byte in label(q) and go to q,  for each q in follow(p)
+///     accept in every p in last, and in 0 if the regex is nullable
+///
+/// Every transition into q is on label(q), so a state only needs its label and the set of
+/// states it can go to; there's no per-state limit on transitions like `Nfa` has.
+pub(crate) struct Glushkov<const N: usize> {
+    labels: [ByteSet; N],
+    next: [StateSet; N],
+    accepting: StateSet,
+    state_count: NFASize,
+    anchors: Anchors,
+}
+
+impl<const N: usize> Glushkov<N> {
+    pub(crate) fn from_regex_bytes(input: &'static [u8]) -> Self {
+        let positions = Syntax::from_regex_bytes(input).positions();
+        let state_count = positions.count as usize + 1;
+        if state_count > N {
+            panic!("too many NFA states");
+        }
+
+        let mut glushkov = Self {
+            labels: [StateSet::new(); N],
+            next: [StateSet::new(); N],
+            accepting: positions.last,
+            state_count: state_count as NFASize,
+            anchors: positions.anchors,
+        };
+        glushkov.labels[..state_count].copy_from_slice(&positions.labels[..state_count]);
+        glushkov.next[..state_count].copy_from_slice(&positions.follow[..state_count]);
+        glushkov.next[0] = positions.first;
+        if positions.nullable {
+            glushkov.accepting.insert(0);
+        }
+        glushkov
+    }
+
+    pub(crate) fn state_count(&self) -> NFASize {
+        self.state_count
+    }
+
+    // The states reachable from any of `set` on `chara`.
+    fn step(&self, set: &StateSet, chara: u8) -> StateSet {
+        let mut result = StateSet::new();
+        for p in set.iter() {
+            for q in self.next[p as usize].iter() {
+                if self.labels[q as usize].contains(chara) {
+                    result.insert(q);
+                }
+            }
+        }
+        result
+    }
+
+    fn accepts(&self, set: &StateSet) -> bool {
+        set.iter().any(|p| self.accepting.contains(p))
+    }
+}
+
+impl<const N: usize, const C: usize> Dfa<N, C> {
+    /// The subset construction again (see `add_start`), over a Glushkov NFA. With no
+    /// ε-transitions, U is just move(T, a).
+    pub(crate) fn from_glushkov<const M: usize>(glushkov: &Glushkov<M>) -> Self {
+        let mut dfa = Self::new();
+        dfa.classes =
+            ByteClasses::from_byte_sets(&glushkov.labels[1..glushkov.state_count as usize]);
+        if dfa.classes.count() > C {
+            panic!("too many byte classes");
+        }
+
+        let mut seed = StateSet::new();
+        seed.insert(0);
+        dfa.start_idx = dfa.intern(seed, glushkov.accepting.contains(0));
+        dfa.anchors = glushkov.anchors;
+
+        while dfa.marked_count < dfa.state_count {
+            let t = dfa.marked_count;
+            dfa.marked_count += 1;
+
+            for class in 0..dfa.classes.count() {
+                let (chara, _) = dfa.classes.range(class as u8);
+                let u = glushkov.step(&dfa.nfa_states[t as usize], chara);
+                let u = dfa.intern(u, glushkov.accepts(&u));
+                dfa.set_transition(t, class, u);
+            }
+        }
+
+        dfa
+    }
+}
@@ -0,0 +1,556 @@
+use super::{Anchor, Anchors, ByteClasses, DFASize, Dfa, NFASize, Nfa, TRANSITIONS_PER_STATE};
+use crate::io::{itoa, Read, Write};
+
+// JSON import/export for automata, so they can be handed to other tools and read back.
+//
+// Both automata use the same shape:
+//
+//     {
+//       "type": "nfa",
+//       "states": 4,
+//       "start": 0,
+//       "accepting": [3],
+//       "transitions": [
+//         {"from": 0, "on": "a", "to": 1},
+//         {"from": 1, "on": null, "to": 3}
+//       ]
+//     }
+//
+// States are numbered 0..states. "on" is a one-symbol string, a ["lo", "hi"] pair of them for an
+// inclusive range, or null for ε. Symbols are bytes; anything outside printable ASCII is written
+// as "\u00XX". A DFA has no ε-transitions, and its state 0 is the dead state: any transition that
+// isn't listed goes there.
+//
+// An automaton built from an anchored pattern also has "start_anchor" and/or "end_anchor" keys,
+// set to "text" (for "^"/"$") or "line" (for "^"/"$" under (?m)).
+
+impl<const N: usize> Nfa<N> {
+    pub(crate) fn write_json<W: Write>(&self, out: &mut W) {
+        write_header(
+            out,
+            "nfa",
+            self.state_count as u32,
+            self.start_idx as u32,
+            self.anchors,
+        );
+        out.puts(itoa(self.accept_idx as u32));
+        out.puts("],\n  \"transitions\": [");
+
+        let mut first = true;
+        for (idx, state) in self.states[0..self.state_count as usize].iter().enumerate() {
+            for transition in &state.transitions[0..state.transition_count as usize] {
+                write_transition(
+                    out,
+                    &mut first,
+                    idx as u32,
+                    transition.on_range,
+                    transition.to_state_idx as u32,
+                );
+            }
+        }
+        write_footer(out, first);
+    }
+
+    /// Load an NFA written by `write_json`. Returns `None` if the input isn't well-formed, or
+    /// describes something this `Nfa` can't hold: more than `N` states, more than one accepting
+    /// state, or more than `TRANSITIONS_PER_STATE` transitions out of a state.
+    pub(crate) fn read_json<R: Read>(input: &mut R) -> Option<Self> {
+        let mut nfa = Self::new();
+        let mut accept_count = 0;
+
+        let header = read_object(input, |key, input| {
+            match key {
+                b"accepting" => {
+                    read_array(input, |input| {
+                        nfa.accept_idx = read_state(input, N)? as NFASize;
+                        accept_count += 1;
+                        Some(())
+                    })?;
+                }
+                b"transitions" => {
+                    read_array(input, |input| {
+                        let (from, on, to) = read_transition(input, N)?;
+                        let state = &mut nfa.states[from];
+                        if state.transition_count as usize == TRANSITIONS_PER_STATE {
+                            return None;
+                        }
+                        *state = state.add_transition(on, to as NFASize);
+                        Some(())
+                    })?;
+                }
+                _ => return None,
+            }
+            Some(())
+        })?;
+
+        if header.kind != Some(Kind::Nfa) || accept_count != 1 {
+            return None;
+        }
+        let state_count = header.states?;
+        if state_count > N || state_count > NFASize::MAX as usize {
+            return None;
+        }
+        nfa.state_count = state_count as NFASize;
+        nfa.start_idx = header.start? as NFASize;
+        nfa.anchors = header.anchors;
+
+        // Everything has to point at a state that exists.
+        let in_bounds = |idx: NFASize| (idx as usize) < state_count;
+        if !in_bounds(nfa.start_idx) || !in_bounds(nfa.accept_idx) {
+            return None;
+        }
+        for (idx, state) in nfa.states.iter().enumerate() {
+            let transitions = &state.transitions[0..state.transition_count as usize];
+            if !transitions.is_empty() && idx >= state_count {
+                return None;
+            }
+            if !transitions
+                .iter()
+                .all(|transition| in_bounds(transition.to_state_idx))
+            {
+                return None;
+            }
+        }
+
+        Some(nfa)
+    }
+}
+
+impl<const N: usize, const C: usize> Dfa<N, C> {
+    pub(crate) fn write_json<W: Write>(&self, out: &mut W) {
+        write_header(
+            out,
+            "dfa",
+            self.state_count as u32,
+            self.start_idx as u32,
+            self.anchors,
+        );
+        let mut first = true;
+        for idx in self.accepting.iter() {
+            if !first {
+                out.puts(", ");
+            }
+            first = false;
+            out.puts(itoa(idx as u32));
+        }
+        out.puts("],\n  \"transitions\": [");
+
+        let mut first = true;
+        for idx in 0..self.state_count {
+            for (lo, hi, target) in self.byte_runs(idx) {
+                write_transition(out, &mut first, idx as u32, Some((lo, hi)), target as u32);
+            }
+        }
+        write_footer(out, first);
+    }
+
+    /// Load a DFA written by `write_json`. Returns `None` if the input isn't well-formed, has
+    /// more than `N` states or `C` byte classes, or contains an ε-transition. The loaded DFA
+    /// doesn't know which NFA states its states came from.
+    pub(crate) fn read_json<R: Read>(input: &mut R) -> Option<Self> {
+        let mut dfa = Self::new();
+        // The byte classes aren't known until every transition has been read, so collect a
+        // column per byte first.
+        let mut by_byte = [[Self::DEAD; 256]; N];
+
+        let header = read_object(input, |key, input| {
+            match key {
+                b"accepting" => {
+                    read_array(input, |input| {
+                        dfa.accepting.insert(read_state(input, N)? as DFASize);
+                        Some(())
+                    })?;
+                }
+                b"transitions" => {
+                    read_array(input, |input| {
+                        let (from, on, to) = read_transition(input, N)?;
+                        let (lo, hi) = on?;
+                        for chara in lo..=hi {
+                            by_byte[from][chara as usize] = to as DFASize;
+                        }
+                        Some(())
+                    })?;
+                }
+                _ => return None,
+            }
+            Some(())
+        })?;
+
+        if header.kind != Some(Kind::Dfa) {
+            return None;
+        }
+        let state_count = header.states?;
+        if state_count == 0 || state_count > N || state_count > DFASize::MAX as usize {
+            return None;
+        }
+        dfa.state_count = state_count as DFASize;
+        dfa.marked_count = dfa.state_count;
+        dfa.start_idx = header.start? as DFASize;
+        dfa.anchors = header.anchors;
+
+        let in_bounds = |idx: DFASize| (idx as usize) < state_count;
+        if !in_bounds(dfa.start_idx) || !dfa.accepting.iter().all(in_bounds) {
+            return None;
+        }
+        for (idx, row) in by_byte.iter().enumerate() {
+            if idx >= state_count && row.iter().any(|target| *target != Self::DEAD) {
+                return None;
+            }
+            if !row.iter().copied().all(in_bounds) {
+                return None;
+            }
+        }
+
+        let rows = &by_byte[0..state_count];
+        dfa.classes = ByteClasses::from_boundaries(|chara| {
+            rows.iter()
+                .any(|row| row[chara as usize - 1] != row[chara as usize])
+        });
+        if state_count * dfa.classes.count() > C {
+            return None;
+        }
+        for (state, by_byte) in rows.iter().enumerate() {
+            for (chara, target) in by_byte.iter().enumerate() {
+                dfa.set_transition(
+                    state as DFASize,
+                    dfa.classes.class(chara as u8) as usize,
+                    *target,
+                );
+            }
+        }
+
+        Some(dfa)
+    }
+}
+
+fn write_header<W: Write>(out: &mut W, kind: &str, states: u32, start: u32, anchors: Anchors) {
+    out.puts("{\n  \"type\": \"");
+    out.puts(kind);
+    out.puts("\",\n  \"states\": ");
+    out.puts(itoa(states));
+    out.puts(",\n  \"start\": ");
+    out.puts(itoa(start));
+    for (key, anchor) in [("start_anchor", anchors.start), ("end_anchor", anchors.end)] {
+        let value = match anchor {
+            Anchor::None => continue,
+            Anchor::Text => "text",
+            Anchor::Line => "line",
+        };
+        out.puts(",\n  \"");
+        out.puts(key);
+        out.puts("\": \"");
+        out.puts(value);
+        out.puts("\"");
+    }
+    out.puts(",\n  \"accepting\": [");
+}
+
+fn write_transition<W: Write>(
+    out: &mut W,
+    first: &mut bool,
+    from: u32,
+    on: Option<(u8, u8)>,
+    to: u32,
+) {
+    out.puts(if *first { "\n" } else { ",\n" });
+    *first = false;
+
+    out.puts("    {\"from\": ");
+    out.puts(itoa(from));
+    out.puts(", \"on\": ");
+    match on {
+        None => {
+            out.puts("null");
+        }
+        Some((lo, hi)) if lo == hi => write_symbol(out, lo),
+        Some((lo, hi)) => {
+            out.puts("[");
+            write_symbol(out, lo);
+            out.puts(", ");
+            write_symbol(out, hi);
+            out.puts("]");
+        }
+    }
+    out.puts(", \"to\": ");
+    out.puts(itoa(to));
+    out.puts("}");
+}
+
+fn write_symbol<W: Write>(out: &mut W, chara: u8) {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    match chara {
+        b'"' => {
+            out.puts("\"\\\"\"");
+        }
+        b'\\' => {
+            out.puts("\"\\\\\"");
+        }
+        b' '..=b'~' => {
+            out.puts([b'"', chara, b'"']);
+        }
+        _ => {
+            out.puts("\"\\u00");
+            out.puts([
+                HEX[(chara >> 4) as usize],
+                HEX[(chara & 0xf) as usize],
+                b'"',
+            ]);
+        }
+    }
+}
+
+fn write_footer<W: Write>(out: &mut W, empty: bool) {
+    out.puts(if empty { "]\n}\n" } else { "\n  ]\n}\n" });
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Nfa,
+    Dfa,
+}
+
+// One transition as read: the state it's from, what it's on (None for ε) and the state it's to.
+type JsonTransition = (usize, Option<(u8, u8)>, usize);
+
+// The keys both automata have in common; the rest are handed to the caller of `read_object`.
+struct Header {
+    kind: Option<Kind>,
+    states: Option<usize>,
+    start: Option<usize>,
+    anchors: Anchors,
+}
+
+// Read a top-level automaton object. "type", "states", "start" and the anchors are handled here;
+// every other key is passed to `field`, which must consume its value.
+fn read_object<R: Read>(
+    input: &mut R,
+    mut field: impl FnMut(&[u8], &mut R) -> Option<()>,
+) -> Option<Header> {
+    let mut header = Header {
+        kind: None,
+        states: None,
+        start: None,
+        anchors: Anchors::NONE,
+    };
+    let mut key_buf = [0u8; 16];
+
+    expect(input, b'{')?;
+    if peek(input)? == b'}' {
+        input.getc();
+    } else {
+        loop {
+            let key = read_string(input, &mut key_buf)?;
+            expect(input, b':')?;
+            match key {
+                b"type" => {
+                    let mut value_buf = [0u8; 3];
+                    header.kind = match read_string(input, &mut value_buf)? {
+                        b"nfa" => Some(Kind::Nfa),
+                        b"dfa" => Some(Kind::Dfa),
+                        _ => return None,
+                    };
+                }
+                b"states" => header.states = Some(read_number(input)?),
+                b"start" => header.start = Some(read_number(input)?),
+                b"start_anchor" => header.anchors.start = read_anchor(input)?,
+                b"end_anchor" => header.anchors.end = read_anchor(input)?,
+                _ => field(key, input)?,
+            }
+
+            match next(input)? {
+                b',' => continue,
+                b'}' => break,
+                _ => return None,
+            }
+        }
+    }
+
+    // Nothing but whitespace may follow.
+    if peek(input).is_some() {
+        return None;
+    }
+
+    Some(header)
+}
+
+fn read_anchor<R: Read>(input: &mut R) -> Option<Anchor> {
+    let mut buf = [0u8; 4];
+    match read_string(input, &mut buf)? {
+        b"text" => Some(Anchor::Text),
+        b"line" => Some(Anchor::Line),
+        _ => None,
+    }
+}
+
+fn read_array<R: Read>(input: &mut R, mut element: impl FnMut(&mut R) -> Option<()>) -> Option<()> {
+    expect(input, b'[')?;
+    if peek(input)? == b']' {
+        input.getc();
+        return Some(());
+    }
+
+    loop {
+        element(input)?;
+        match next(input)? {
+            b',' => continue,
+            b']' => return Some(()),
+            _ => return None,
+        }
+    }
+}
+
+// {"from": 0, "on": "a", "to": 1}, in any key order.
+fn read_transition<R: Read>(input: &mut R, limit: usize) -> Option<JsonTransition> {
+    let (mut from, mut on, mut to) = (None, None, None);
+    let mut key_buf = [0u8; 4];
+
+    expect(input, b'{')?;
+    loop {
+        let key = read_string(input, &mut key_buf)?;
+        expect(input, b':')?;
+        match key {
+            b"from" => from = Some(read_state(input, limit)?),
+            b"to" => to = Some(read_state(input, limit)?),
+            b"on" => on = Some(read_symbol(input)?),
+            _ => return None,
+        }
+
+        match next(input)? {
+            b',' => continue,
+            b'}' => break,
+            _ => return None,
+        }
+    }
+
+    Some((from?, on?, to?))
+}
+
+fn read_state<R: Read>(input: &mut R, limit: usize) -> Option<usize> {
+    let idx = read_number(input)?;
+    if idx >= limit {
+        return None;
+    }
+    Some(idx)
+}
+
+fn read_number<R: Read>(input: &mut R) -> Option<usize> {
+    skip_whitespace(input);
+    let mut number: usize = 0;
+    let mut digits = 0;
+    while let Some(chara @ b'0'..=b'9') = input.peek() {
+        input.getc();
+        number = number
+            .checked_mul(10)?
+            .checked_add((chara - b'0') as usize)?;
+        digits += 1;
+    }
+
+    if digits == 0 {
+        return None;
+    }
+    Some(number)
+}
+
+// null, a string holding exactly one symbol, or a ["lo", "hi"] range of them.
+fn read_symbol<R: Read>(input: &mut R) -> Option<Option<(u8, u8)>> {
+    match peek(input)? {
+        b'n' => {
+            for chara in b"null" {
+                if input.getc()? != *chara {
+                    return None;
+                }
+            }
+            Some(None)
+        }
+        b'[' => {
+            input.getc();
+            let lo = read_one_symbol(input)?;
+            expect(input, b',')?;
+            let hi = read_one_symbol(input)?;
+            expect(input, b']')?;
+            if lo > hi {
+                return None;
+            }
+            Some(Some((lo, hi)))
+        }
+        _ => {
+            let chara = read_one_symbol(input)?;
+            Some(Some((chara, chara)))
+        }
+    }
+}
+
+fn read_one_symbol<R: Read>(input: &mut R) -> Option<u8> {
+    let mut buf = [0u8; 1];
+    match read_string(input, &mut buf)? {
+        [chara] => Some(*chara),
+        _ => None,
+    }
+}
+
+// Read a string into `buf`. Fails if it doesn't fit, or if it uses an escape for a code point
+// above U+00FF: every character has to stand for a single byte.
+fn read_string<'a, R: Read>(input: &mut R, buf: &'a mut [u8]) -> Option<&'a [u8]> {
+    expect(input, b'"')?;
+    let mut len = 0;
+    loop {
+        let chara = match input.getc()? {
+            b'"' => return Some(&buf[0..len]),
+            b'\\' => match input.getc()? {
+                b'"' => b'"',
+                b'\\' => b'\\',
+                b'/' => b'/',
+                b'b' => 0x08,
+                b'f' => 0x0c,
+                b'n' => b'\n',
+                b'r' => b'\r',
+                b't' => b'\t',
+                b'u' => {
+                    let mut code_point: u32 = 0;
+                    for _ in 0..4 {
+                        let digit = match input.getc()? {
+                            digit @ b'0'..=b'9' => digit - b'0',
+                            digit @ b'a'..=b'f' => digit - b'a' + 10,
+                            digit @ b'A'..=b'F' => digit - b'A' + 10,
+                            _ => return None,
+                        };
+                        code_point = (code_point << 4) | digit as u32;
+                    }
+                    u8::try_from(code_point).ok()?
+                }
+                _ => return None,
+            },
+            // Control characters must be escaped, and we only deal in single-byte symbols.
+            0x00..=0x1f | 0x80..=0xff => return None,
+            chara => chara,
+        };
+
+        *buf.get_mut(len)? = chara;
+        len += 1;
+    }
+}
+
+fn skip_whitespace<R: Read>(input: &mut R) {
+    while let Some(b' ' | b'\t' | b'\n' | b'\r') = input.peek() {
+        input.getc();
+    }
+}
+
+fn peek<R: Read>(input: &mut R) -> Option<u8> {
+    skip_whitespace(input);
+    input.peek()
+}
+
+fn next<R: Read>(input: &mut R) -> Option<u8> {
+    skip_whitespace(input);
+    input.getc()
+}
+
+fn expect<R: Read>(input: &mut R, chara: u8) -> Option<()> {
+    if next(input)? == chara {
+        Some(())
+    } else {
+        None
+    }
+}
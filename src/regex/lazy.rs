@@ -0,0 +1,227 @@
+use super::{r#move, ε_closure, ByteClasses, DFASize, Nfa, StateSet};
+
+// How often one search may throw the cache away before we decide that caching isn't paying
+// for itself on this input and finish the search by plain NFA simulation.
+const MAX_CLEARS_PER_SEARCH: usize = 4;
+
+const DEAD: DFASize = 0;
+const UNKNOWN: DFASize = DFASize::MAX;
+
+/// A DFA that is built while it runs.
+///
+/// The subset construction decides, up front, what the DFA does on every input. Most of those
+/// states are never visited by any particular input, and for some patterns there are
+/// exponentially many of them: `(a|b)*a(a|b)(a|b)` ... needs a state for every combination of
+/// the last few bytes. A lazy DFA instead computes ε_closure(move(T, a)) the first time it
+/// actually takes the transition from T on a, and remembers the answer.
+///
+/// At most `C` states are remembered. When the cache is full it is cleared and rebuilt from the
+/// state we are in; if that happens more than `MAX_CLEARS_PER_SEARCH` times in one search, the
+/// rest of the input is handled by NFA simulation, one state set at a time, without caching.
+pub(crate) struct LazyDfa<'a, const N: usize, const C: usize> {
+    nfa: &'a Nfa<N>,
+    nfa_states: [StateSet; C],
+    classes: ByteClasses,
+    // Indexed by byte class; UNKNOWN until the transition is first taken.
+    transitions: [[DFASize; 256]; C],
+    accepting: StateSet,
+    state_count: DFASize,
+    start_idx: DFASize,
+    stats: LazyStats,
+}
+
+/// What the cache has been up to, for tuning `C`.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct LazyStats {
+    /// Transitions computed, as opposed to looked up.
+    pub(crate) misses: usize,
+    pub(crate) clears: usize,
+    /// Searches finished by NFA simulation.
+    pub(crate) fallbacks: usize,
+}
+
+// Where a search is: a cached state, or (after giving up on the cache) a set of NFA states.
+#[derive(Clone, Copy)]
+enum Position {
+    Cached(DFASize),
+    Simulated(StateSet),
+}
+
+impl<'a, const N: usize, const C: usize> LazyDfa<'a, N, C> {
+    const CAPACITY: usize = if C < 4 {
+        // dead state + start state + the state we clear from + the state we clear for
+        panic!("C must be at least 4")
+    } else if C > UNKNOWN as usize {
+        panic!("C must be less than DFASize::MAX")
+    } else {
+        C
+    };
+
+    pub(crate) fn new(nfa: &'a Nfa<N>) -> Self {
+        let mut lazy = Self {
+            nfa,
+            nfa_states: [StateSet::new(); C],
+            classes: ByteClasses::from_nfa(nfa),
+            transitions: [[UNKNOWN; 256]; C],
+            accepting: StateSet::new(),
+            state_count: 0,
+            start_idx: DEAD,
+            stats: LazyStats::default(),
+        };
+        lazy.clear();
+        lazy
+    }
+
+    pub(crate) fn stats(&self) -> LazyStats {
+        self.stats
+    }
+
+    /// How many states are in the cache right now, counting the dead state.
+    pub(crate) fn state_count(&self) -> usize {
+        self.state_count as usize
+    }
+
+    // Forget everything but the dead state and the start state.
+    fn clear(&mut self) {
+        self.accepting = StateSet::new();
+        self.transitions[DEAD as usize] = [DEAD; 256];
+        self.nfa_states[DEAD as usize] = StateSet::new();
+        self.state_count = 1;
+
+        let mut seed = StateSet::new();
+        seed.insert(self.nfa.start_idx);
+        self.start_idx = self
+            .intern(ε_closure(self.nfa, seed))
+            .expect("a fresh cache has room for the start state");
+    }
+
+    // Find or add the cached state for `set`; `None` if the cache is full.
+    fn intern(&mut self, set: StateSet) -> Option<DFASize> {
+        if set.is_empty() {
+            return Some(DEAD);
+        }
+
+        for idx in 1..self.state_count {
+            if self.nfa_states[idx as usize] == set {
+                return Some(idx);
+            }
+        }
+
+        if self.state_count as usize == Self::CAPACITY {
+            return None;
+        }
+
+        let idx = self.state_count;
+        self.state_count += 1;
+        self.nfa_states[idx as usize] = set;
+        self.transitions[idx as usize] = [UNKNOWN; 256];
+        if set.contains(self.nfa.accept_idx) {
+            self.accepting.insert(idx);
+        }
+        Some(idx)
+    }
+
+    fn advance(&mut self, position: Position, chara: u8, clears: &mut usize) -> Position {
+        let state = match position {
+            Position::Simulated(set) => {
+                return Position::Simulated(ε_closure(self.nfa, r#move(self.nfa, &set, chara)));
+            }
+            Position::Cached(state) => state,
+        };
+
+        let class = self.classes.class(chara) as usize;
+        let next = self.transitions[state as usize][class];
+        if next != UNKNOWN {
+            return Position::Cached(next);
+        }
+
+        self.stats.misses += 1;
+        let from = self.nfa_states[state as usize];
+        let to = ε_closure(self.nfa, r#move(self.nfa, &from, chara));
+        if let Some(next) = self.intern(to) {
+            self.transitions[state as usize][class] = next;
+            return Position::Cached(next);
+        }
+
+        // The cache is full.
+        if *clears == MAX_CLEARS_PER_SEARCH {
+            self.stats.fallbacks += 1;
+            return Position::Simulated(to);
+        }
+        *clears += 1;
+        self.stats.clears += 1;
+        self.clear();
+
+        let state = self.intern(from).expect("a fresh cache has room");
+        let next = self.intern(to).expect("a fresh cache has room");
+        self.transitions[state as usize][class] = next;
+        Position::Cached(next)
+    }
+
+    fn is_accepting(&self, position: &Position) -> bool {
+        match position {
+            Position::Cached(state) => self.accepting.contains(*state),
+            Position::Simulated(set) => set.contains(self.nfa.accept_idx),
+        }
+    }
+
+    fn is_dead(position: &Position) -> bool {
+        match position {
+            Position::Cached(state) => *state == DEAD,
+            Position::Simulated(set) => set.is_empty(),
+        }
+    }
+
+    /// Whether the whole of `input` matches.
+    pub(crate) fn matches(&mut self, input: &[u8]) -> bool {
+        let mut clears = 0;
+        let mut position = Position::Cached(self.start_idx);
+        for chara in input {
+            position = self.advance(position, *chara, &mut clears);
+            if Self::is_dead(&position) {
+                return false;
+            }
+        }
+        self.is_accepting(&position)
+    }
+
+    /// Find the leftmost-longest match in `haystack`, like `Dfa::find`.
+    pub(crate) fn find(&mut self, haystack: &[u8]) -> Option<(usize, usize)> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Like `find`, but only for matches that start at `from` or later; see
+    /// `ReverseSearch::find_at`.
+    pub(crate) fn find_at(&mut self, haystack: &[u8], from: usize) -> Option<(usize, usize)> {
+        let anchors = self.nfa.anchors;
+        let mut clears = 0;
+        for start in from..=haystack.len() {
+            if !anchors.can_start_at(haystack, start) {
+                continue;
+            }
+
+            let mut position = Position::Cached(self.start_idx);
+            let mut end = None;
+            let mut idx = start;
+            loop {
+                if self.is_accepting(&position) && anchors.can_end_at(haystack, idx) {
+                    end = Some(idx);
+                }
+                if idx == haystack.len() {
+                    break;
+                }
+                position = self.advance(position, haystack[idx], &mut clears);
+                if Self::is_dead(&position) {
+                    break;
+                }
+                idx += 1;
+            }
+
+            if let Some(end) = end {
+                return Some((start, end));
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,126 @@
+use super::{ByteClasses, DFASize, Dfa, StateSet};
+
+impl<const N: usize, const C: usize> Dfa<N, C> {
+    /// The DFA with the fewest states that matches the same strings.
+    ///
+    /// This is the partition refinement from "Compilers: Principles, Tools, and Techniques":
+    ///
+    ///     start with two groups: the accepting states and the rest
+    ///     repeat; do
+    ///       split each group so that two states stay together only if, on every input
+    ///       symbol, they go to states in the same group
+    ///     until no group is split
+    ///
+    /// then keep one state per group. Along the way, states that can't be reached are dropped,
+    /// states that can never accept fold into the dead state, and byte classes that no longer
+    /// make a difference are merged. A merged state's NFA states are the union of its members'.
+    pub(crate) fn minimize(&self) -> Self {
+        let state_count = self.state_count as usize;
+        let class_count = self.classes.count();
+
+        // group[s] names the group of state s by its lowest-numbered member.
+        let mut group = [Self::DEAD; N];
+        for state in 0..self.state_count {
+            group[state as usize] = (0..=state)
+                .find(|other| self.is_accepting(*other) == self.is_accepting(state))
+                .expect("a state is in its own group");
+        }
+
+        loop {
+            let mut next = [Self::DEAD; N];
+            for state in 0..self.state_count {
+                next[state as usize] = (0..=state)
+                    .find(|other| {
+                        group[*other as usize] == group[state as usize]
+                            && (0..class_count).all(|class| {
+                                let a = self.transition(*other, class);
+                                let b = self.transition(state, class);
+                                group[a as usize] == group[b as usize]
+                            })
+                    })
+                    .expect("a state is in its own group");
+            }
+            if next[0..state_count] == group[0..state_count] {
+                break;
+            }
+            group = next;
+        }
+
+        // Number the groups: the dead state's group stays 0, and the rest are numbered in the
+        // order a breadth-first walk from the start state reaches them.
+        const UNNUMBERED: DFASize = DFASize::MAX;
+        let mut number = [UNNUMBERED; N];
+        let mut order = [Self::DEAD; N];
+        number[group[Self::DEAD as usize] as usize] = Self::DEAD;
+        let mut count = 1;
+        let start_group = group[self.start_idx as usize];
+        if number[start_group as usize] == UNNUMBERED {
+            number[start_group as usize] = count as DFASize;
+            order[count] = start_group;
+            count += 1;
+        }
+        let mut visited = 1;
+        while visited < count {
+            let state = order[visited];
+            visited += 1;
+            for class in 0..class_count {
+                let target = group[self.transition(state, class) as usize];
+                if number[target as usize] == UNNUMBERED {
+                    number[target as usize] = count as DFASize;
+                    order[count] = target;
+                    count += 1;
+                }
+            }
+        }
+
+        let mut dfa = Self::new();
+        dfa.state_count = count as DFASize;
+        dfa.marked_count = dfa.state_count;
+        dfa.start_idx = number[start_group as usize];
+        dfa.anchors = self.anchors;
+        for state in 0..self.state_count {
+            let idx = number[group[state as usize] as usize];
+            if idx == UNNUMBERED || idx == Self::DEAD {
+                continue;
+            }
+            dfa.nfa_states[idx as usize] = union(
+                &dfa.nfa_states[idx as usize],
+                &self.nfa_states[state as usize],
+            );
+            if self.is_accepting(state) {
+                dfa.accepting.insert(idx);
+            }
+        }
+
+        // Two neighbouring classes can be merged when every new state treats them the same.
+        let column = |idx: usize, class: usize| {
+            let target = self.transition(order[idx], class);
+            number[group[target as usize] as usize]
+        };
+        dfa.classes = ByteClasses::from_boundaries(|chara| {
+            let (before, after) = (self.classes.class(chara - 1), self.classes.class(chara));
+            before != after
+                && (1..count).any(|idx| column(idx, before as usize) != column(idx, after as usize))
+        });
+        for idx in 1..count {
+            for chara in 0..=255u8 {
+                let class = self.classes.class(chara) as usize;
+                dfa.set_transition(
+                    idx as DFASize,
+                    dfa.classes.class(chara) as usize,
+                    column(idx, class),
+                );
+            }
+        }
+
+        dfa
+    }
+}
+
+fn union(a: &StateSet, b: &StateSet) -> StateSet {
+    let mut set = *a;
+    for idx in b.iter() {
+        set.insert(idx);
+    }
+    set
+}
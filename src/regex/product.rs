@@ -0,0 +1,160 @@
+use super::{Anchors, ByteClasses, DFASize, Dfa};
+
+// Set operations on regular languages, by the product construction: run both DFAs side by side,
+// one state of the result for each pair (p, q) of their states that the input can reach, and
+// decide whether a pair accepts from whether p and q do.
+//
+// The result is an ordinary `Dfa`, but it doesn't come from an NFA, so its states have no NFA
+// state sets. It is rarely minimal; see `Dfa::minimize`.
+
+impl<const N: usize, const C: usize> Dfa<N, C> {
+    /// A DFA for the strings both `a` and `b` match.
+    pub(crate) fn intersection<const M: usize, const CM: usize, const K: usize, const CK: usize>(
+        a: &Dfa<M, CM>,
+        b: &Dfa<K, CK>,
+    ) -> Self {
+        Self::product(a, b, |in_a, in_b| in_a && in_b)
+    }
+
+    /// A DFA for the strings either `a` or `b` matches.
+    pub(crate) fn union<const M: usize, const CM: usize, const K: usize, const CK: usize>(
+        a: &Dfa<M, CM>,
+        b: &Dfa<K, CK>,
+    ) -> Self {
+        Self::product(a, b, |in_a, in_b| in_a || in_b)
+    }
+
+    /// A DFA for the strings `a` matches and `b` doesn't.
+    pub(crate) fn difference<const M: usize, const CM: usize, const K: usize, const CK: usize>(
+        a: &Dfa<M, CM>,
+        b: &Dfa<K, CK>,
+    ) -> Self {
+        Self::product(a, b, |in_a, in_b| in_a && !in_b)
+    }
+
+    /// A DFA for the strings over `alphabet` (a list of inclusive byte ranges) that `a` doesn't
+    /// match. Bytes outside the alphabet still never match.
+    ///
+    /// The alphabet is bytes, so a complement over [0x00-0xff] is full of invalid UTF-8. To
+    /// complement within UTF-8 text, take the difference from a DFA for "(?s).*" instead.
+    pub(crate) fn complement<const M: usize, const CM: usize>(
+        a: &Dfa<M, CM>,
+        alphabet: &[(u8, u8)],
+    ) -> Self {
+        Self::difference(&Dfa::<2>::universal(alphabet, a.anchors), a)
+    }
+
+    // Σ*: one accepting state that loops on every byte of `alphabet`.
+    fn universal(alphabet: &[(u8, u8)], anchors: Anchors) -> Self {
+        let mut in_alphabet = [false; 256];
+        for (lo, hi) in alphabet {
+            for chara in *lo..=*hi {
+                in_alphabet[chara as usize] = true;
+            }
+        }
+
+        let mut dfa = Self::new();
+        dfa.classes = ByteClasses::from_boundaries(|chara| {
+            in_alphabet[chara as usize - 1] != in_alphabet[chara as usize]
+        });
+        dfa.state_count = 2;
+        dfa.marked_count = 2;
+        dfa.start_idx = 1;
+        dfa.accepting.insert(1);
+        dfa.anchors = anchors;
+        for chara in 0..=255u8 {
+            if in_alphabet[chara as usize] {
+                dfa.set_transition(1, dfa.classes.class(chara) as usize, 1);
+            }
+        }
+        dfa
+    }
+
+    // The product of `a` and `b`, where a pair accepts if `accepts(p accepts, q accepts)`.
+    //
+    // A pair is left out (it becomes the dead state) when `accepts` says no however the rest of
+    // the input goes: a DFA in its dead state will never accept again.
+    pub(super) fn product<const M: usize, const CM: usize, const K: usize, const CK: usize>(
+        a: &Dfa<M, CM>,
+        b: &Dfa<K, CK>,
+        accepts: impl Fn(bool, bool) -> bool,
+    ) -> Self {
+        match Self::try_product(a, b, accepts) {
+            Some(dfa) => dfa,
+            None => panic!("too many DFA states"),
+        }
+    }
+
+    // Like `product`, but `None` instead of a panic when there are more than `N` pairs.
+    pub(super) fn try_product<const M: usize, const CM: usize, const K: usize, const CK: usize>(
+        a: &Dfa<M, CM>,
+        b: &Dfa<K, CK>,
+        accepts: impl Fn(bool, bool) -> bool,
+    ) -> Option<Self> {
+        if a.anchors != b.anchors {
+            panic!("can't combine DFAs with different anchors");
+        }
+
+        let mut dfa = Self::new();
+        dfa.classes = a.classes.refine(&b.classes);
+        if dfa.classes.count() > C {
+            panic!("too many byte classes");
+        }
+        dfa.anchors = a.anchors;
+
+        let is_dead = |p: DFASize, q: DFASize| {
+            let a_futures: &[bool] = if p == Dfa::<M, CM>::DEAD {
+                &[false]
+            } else {
+                &[false, true]
+            };
+            let b_futures: &[bool] = if q == Dfa::<K, CK>::DEAD {
+                &[false]
+            } else {
+                &[false, true]
+            };
+            !a_futures
+                .iter()
+                .any(|in_a| b_futures.iter().any(|in_b| accepts(*in_a, *in_b)))
+        };
+
+        // pairs[idx] is the (p, q) that state idx of the result stands for.
+        let mut pairs = [(Self::DEAD, Self::DEAD); N];
+        let intern =
+            |dfa: &mut Self, pairs: &mut [(DFASize, DFASize); N], p: DFASize, q: DFASize| {
+                if is_dead(p, q) {
+                    return Some(Self::DEAD);
+                }
+                if let Some(idx) = (1..dfa.state_count).find(|idx| pairs[*idx as usize] == (p, q)) {
+                    return Some(idx);
+                }
+                if !dfa.has_room() {
+                    return None;
+                }
+                let idx = dfa.state_count;
+                dfa.state_count += 1;
+                pairs[idx as usize] = (p, q);
+                if accepts(a.is_accepting(p), b.is_accepting(q)) {
+                    dfa.accepting.insert(idx);
+                }
+                Some(idx)
+            };
+
+        dfa.start_idx = intern(&mut dfa, &mut pairs, a.start_idx, b.start_idx)?;
+
+        // The same worklist as the subset construction: unmarked states are marked_count..state_count.
+        while dfa.marked_count < dfa.state_count {
+            let t = dfa.marked_count;
+            dfa.marked_count += 1;
+
+            let (p, q) = pairs[t as usize];
+            for class in 0..dfa.classes.count() {
+                let (chara, _) = dfa.classes.range(class as u8);
+                let u = intern(&mut dfa, &mut pairs, a.step(p, chara), b.step(q, chara))?;
+                dfa.set_transition(t, class, u);
+            }
+        }
+
+        Some(dfa)
+    }
+}
@@ -0,0 +1,141 @@
+use super::{Anchor, Anchors, Dfa, NFASize, Nfa, TRANSITIONS_PER_STATE};
+
+impl<const N: usize> Nfa<N> {
+    /// The NFA that matches each string this one matches, backwards: every transition turned
+    /// around, and the start and accepting states swapped. So are the anchors.
+    ///
+    /// A state's transitions in the reversed NFA are the ones that came into it, and a state can
+    /// have more of those than it has room for, so the extras are spread over a chain of new
+    /// states, as `add_branch` does.
+    pub(crate) fn reverse(&self) -> Self {
+        let mut nfa = Self::new();
+        nfa.state_count = self.state_count;
+        nfa.start_idx = self.accept_idx;
+        nfa.accept_idx = self.start_idx;
+        nfa.flags = self.flags;
+        nfa.anchors = Anchors {
+            start: self.anchors.end,
+            end: self.anchors.start,
+        };
+
+        // left[s]: how many of s's reversed transitions are still to be added; tail[s]: the
+        // state of s's chain they go on.
+        let mut left = [0usize; N];
+        for (_, _, to) in self.transitions() {
+            left[to as usize] += 1;
+        }
+        let mut tail = [0 as NFASize; N];
+        for (state, tail) in tail.iter_mut().enumerate() {
+            *tail = state as NFASize;
+        }
+
+        for (from, on, to) in self.transitions() {
+            let mut at = tail[to as usize];
+            let count = nfa.states[at as usize].transition_count as usize;
+            if count == TRANSITIONS_PER_STATE - 1 && left[to as usize] > 1 {
+                let next = nfa.add_branch(&[]);
+                nfa.states[at as usize] = nfa.states[at as usize].add_transition(None, next);
+                at = next;
+                tail[to as usize] = next;
+            }
+            nfa.states[at as usize] = nfa.states[at as usize].add_transition(on, from);
+            left[to as usize] -= 1;
+        }
+        nfa
+    }
+
+    // Let a reversed NFA read past the end of the input that comes after a match before it
+    // starts on the match itself (backwards), as far as the forward pattern's end anchor allows:
+    // anything at all, nothing, or anything up to a "\n".
+    fn skip_to_end(mut self, end: Anchor) -> Self {
+        match end {
+            Anchor::Text => {}
+            Anchor::None => {
+                let skip = self.add_branch(&[self.start_idx]);
+                self.states[skip as usize] =
+                    self.states[skip as usize].add_transition(Some((0, u8::MAX)), skip);
+                self.start_idx = skip;
+            }
+            Anchor::Line => {
+                let skip = self.add_branch(&[]);
+                self.states[skip as usize] = self.states[skip as usize]
+                    .add_transition(Some((0, u8::MAX)), skip)
+                    .add_transition(Some((b'\n', b'\n')), self.start_idx);
+                self.start_idx = self.add_branch(&[self.start_idx, skip]);
+            }
+        }
+        self
+    }
+}
+
+/// Leftmost-longest search, as `Dfa::find` does it, but in two linear passes over the input
+/// where `Dfa::find` may go over it once for every position a match could start at.
+///
+/// The first pass runs a DFA for the reversed pattern backwards over the whole input; it is in
+/// an accepting state just where some match starts, so the last place that happens is where
+/// the leftmost match starts. The second pass runs the ordinary DFA forwards from there, and
+/// the last place it accepts is where the longest match ends. With a `$` anchor, the backwards
+/// pass can only start at the end, so it usually dies within a few bytes.
+pub(crate) struct ReverseSearch<const N: usize> {
+    forward: Dfa<N>,
+    reverse: Dfa<N>,
+}
+
+impl<const N: usize> ReverseSearch<N> {
+    /// `None` when either DFA needs more than `N` states.
+    pub(crate) fn new<const M: usize>(nfa: &Nfa<M>) -> Option<Self> {
+        let reverse = nfa.cleanup().reverse().skip_to_end(nfa.anchors.end);
+        Some(Self {
+            forward: Dfa::try_from_nfa(nfa)?,
+            reverse: Dfa::try_from_nfa(&reverse)?,
+        })
+    }
+
+    /// The same span `Dfa::find` finds.
+    pub(crate) fn find(&self, haystack: &[u8]) -> Option<(usize, usize)> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Like `find`, but only for matches that start at `from` or later, as when looking for the
+    /// next match after one that ended at `from`. Anchors still see all of `haystack`: "^"
+    /// doesn't match at `from` just because the search starts there.
+    pub(crate) fn find_at(&self, haystack: &[u8], from: usize) -> Option<(usize, usize)> {
+        let anchors = self.forward.anchors;
+
+        let mut start = None;
+        let mut state = self.reverse.start_idx;
+        let mut idx = haystack.len();
+        loop {
+            if self.reverse.is_accepting(state) && anchors.can_start_at(haystack, idx) {
+                start = Some(idx);
+            }
+            if idx == from {
+                break;
+            }
+            idx -= 1;
+            state = self.reverse.step(state, haystack[idx]);
+            if state == Dfa::<N>::DEAD {
+                break;
+            }
+        }
+        let start = start?;
+
+        let mut end = start;
+        let mut state = self.forward.start_idx;
+        let mut idx = start;
+        loop {
+            if self.forward.is_accepting(state) && anchors.can_end_at(haystack, idx) {
+                end = idx;
+            }
+            if idx == haystack.len() {
+                break;
+            }
+            state = self.forward.step(state, haystack[idx]);
+            if state == Dfa::<N>::DEAD {
+                break;
+            }
+            idx += 1;
+        }
+        Some((start, end))
+    }
+}
@@ -0,0 +1,503 @@
+use super::syntax::{is_literal, read_symbol, Node, Symbol, Syntax, MAX_NODES};
+use super::{utf8, Anchor, Anchors, Flags, MAX_CLASS_RANGES};
+
+// Rewriting a pattern into a simpler one that matches the same strings. Like the derivatives in
+// derivative.rs, every expression is built by a smart constructor that applies the rewrites as
+// it goes, and expressions are hash-consed, so that "the same expression" is one comparison of
+// indices:
+//
+//     r** = r*          ε* = ε          (ε|r)* = r*
+//     εr = rε = r       r|r = r
+//     a|[bc] = [a-c]                   single characters and classes become one class
+//     ab|ac = a(b|c)                   alternatives that start the same way are factored
+//
+// The syntax tree in syntax.rs only knows bytes, but a simplified pattern has to be printed with
+// characters in it, so the leaves here are symbols (a character, "." or a class) read back from
+// the pattern, as sets of scalar values.
+
+const MAX_EXPRS: usize = 1024;
+
+const MAX_CLASSES: usize = 256;
+
+// How many alternatives one alternation can have.
+const MAX_ALTERNATIVES: usize = 128;
+
+const MAX_PATTERN_LEN: usize = 1024;
+
+type ExprIdx = u16;
+
+type ClassIdx = u16;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Expr {
+    /// ε.
+    Empty,
+    /// One character from the class.
+    Class(ClassIdx),
+    /// rs, where r isn't a concatenation.
+    Concat(ExprIdx, ExprIdx),
+    /// r|s, where r isn't an alternation and isn't ε: ε comes last if it's there at all.
+    Alternate(ExprIdx, ExprIdx),
+    Star(ExprIdx),
+}
+
+/// Scalar values, as sorted inclusive ranges that neither overlap nor touch.
+#[derive(Clone, Copy, PartialEq)]
+struct Class {
+    ranges: [(u32, u32); MAX_CLASS_RANGES],
+    count: usize,
+}
+
+impl Class {
+    const EMPTY: Self = Self {
+        ranges: [(0, 0); MAX_CLASS_RANGES],
+        count: 0,
+    };
+
+    // The class of the scalar values in any of `ranges`, or None if it takes more ranges than
+    // a class in a pattern can have.
+    fn from_ranges(ranges: &mut [(u32, u32)]) -> Option<Self> {
+        ranges.sort_unstable();
+        let mut class = Self::EMPTY;
+        for (lo, hi) in ranges.iter() {
+            if class.count > 0 && *lo <= class.ranges[class.count - 1].1 + 1 {
+                let last = &mut class.ranges[class.count - 1].1;
+                *last = (*last).max(*hi);
+                continue;
+            }
+            if class.count == MAX_CLASS_RANGES {
+                return None;
+            }
+            class.ranges[class.count] = (*lo, *hi);
+            class.count += 1;
+        }
+        Some(class)
+    }
+
+    fn union(&self, other: &Self) -> Option<Self> {
+        let mut ranges = [(0, 0); 2 * MAX_CLASS_RANGES];
+        ranges[..self.count].copy_from_slice(&self.ranges[..self.count]);
+        ranges[self.count..self.count + other.count].copy_from_slice(&other.ranges[..other.count]);
+        Self::from_ranges(&mut ranges[..self.count + other.count])
+    }
+
+    // What `symbol` matches, read back from the pattern.
+    fn from_symbol(input: &'static [u8], symbol: &Symbol) -> Self {
+        let mut ranges = [(0, 0); 3 * MAX_CLASS_RANGES];
+        let (read_ranges, mut count, _) = read_symbol(input, symbol.start, symbol.flags);
+        ranges[..count].copy_from_slice(&read_ranges[..count]);
+
+        // Under (?i), ASCII letters bring their other case along.
+        if symbol.flags.contains(Flags::CASE_INSENSITIVE) {
+            let read = count;
+            for idx in 0..read {
+                let (lo, hi) = ranges[idx];
+                for (other_lo, other_hi) in utf8::other_cases(lo, hi) {
+                    if other_lo <= other_hi {
+                        ranges[count] = (other_lo, other_hi);
+                        count += 1;
+                    }
+                }
+            }
+        }
+
+        match Self::from_ranges(&mut ranges[..count]) {
+            Some(class) => class,
+            None => panic!("too many ranges in class"),
+        }
+    }
+
+    fn is_single(&self) -> bool {
+        self.count == 1 && self.ranges[0].0 == self.ranges[0].1
+    }
+
+    fn print(&self, out: &mut Pattern) {
+        let newline = b'\n' as u32;
+        let ranges = &self.ranges[..self.count];
+        if ranges == [(0, newline - 1), (newline + 1, utf8::MAX_SCALAR)] {
+            out.push(".");
+            return;
+        }
+        if ranges == [(0, utf8::MAX_SCALAR)] {
+            out.push("(?s:.)");
+            return;
+        }
+
+        if self.is_single() {
+            let chara = ranges[0].0;
+            if chara > utf8::MAX_BY_LENGTH[0] {
+                out.push_scalar(chara);
+                return;
+            }
+            match chara as u8 {
+                b'\n' => out.push("\\n"),
+                b'\t' => out.push("\\t"),
+                chara if is_literal(chara) => out.push([chara]),
+                chara @ (b'$' | b'^' | b'(' | b')' | b'{' | b'}' | b'[' | b']' | b'|' | b'?'
+                | b'*' | b'.' | b'\\') => out.push([b'\\', chara]),
+                // Anything else can only be written inside a class.
+                chara => out.push([b'[', chara, b']']),
+            };
+            return;
+        }
+
+        out.push("[");
+        for (lo, hi) in ranges {
+            out.push_class_item(*lo);
+            if *hi == lo + 1 {
+                out.push_class_item(*hi);
+            } else if hi != lo {
+                out.push("-");
+                out.push_class_item(*hi);
+            }
+        }
+        out.push("]");
+    }
+}
+
+// The simplified pattern, as text.
+struct Pattern {
+    bytes: [u8; MAX_PATTERN_LEN],
+    len: usize,
+}
+
+impl Pattern {
+    fn push<T: AsRef<[u8]>>(&mut self, bytes: T) {
+        let bytes = bytes.as_ref();
+        if self.len + bytes.len() > MAX_PATTERN_LEN {
+            panic!("simplified pattern too long");
+        }
+        self.bytes[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    fn push_scalar(&mut self, scalar: u32) {
+        let (bytes, len) = utf8::encode(scalar);
+        self.push(&bytes[..len]);
+    }
+
+    // One end of a range in a class, escaped the way `class_item` reads it.
+    fn push_class_item(&mut self, scalar: u32) {
+        if scalar > utf8::MAX_BY_LENGTH[0] {
+            self.push_scalar(scalar);
+            return;
+        }
+        match scalar as u8 {
+            b'\n' => self.push("\\n"),
+            b'\t' => self.push("\\t"),
+            chara @ (b'[' | b']' | b'^' | b'-' | b'\\') => self.push([b'\\', chara]),
+            chara => self.push([chara]),
+        }
+    }
+}
+
+/// A pattern, simplified.
+pub(crate) struct Simplified {
+    exprs: [Expr; MAX_EXPRS],
+    expr_count: ExprIdx,
+    classes: [Class; MAX_CLASSES],
+    class_count: ClassIdx,
+    root: ExprIdx,
+    anchors: Anchors,
+    pattern: Pattern,
+}
+
+impl Simplified {
+    const EMPTY: ExprIdx = 0;
+
+    pub(crate) fn from_regex_bytes(input: &'static [u8]) -> Self {
+        let syntax = Syntax::from_regex_bytes(input);
+
+        let mut simplified = Self {
+            exprs: [Expr::Empty; MAX_EXPRS],
+            expr_count: 0,
+            classes: [Class::EMPTY; MAX_CLASSES],
+            class_count: 0,
+            root: Self::EMPTY,
+            anchors: syntax.anchors(),
+            pattern: Pattern {
+                bytes: [0; MAX_PATTERN_LEN],
+                len: 0,
+            },
+        };
+        simplified.intern(Expr::Empty);
+
+        // Children come before parents, and a symbol's node after the positions it was compiled
+        // to. Those positions only stand for bytes; the symbol takes their place.
+        let symbols = syntax.symbols();
+        let mut next_symbol = 0;
+        let mut expr_of = [Self::EMPTY; MAX_NODES];
+        for (idx, node) in syntax.nodes().iter().enumerate() {
+            if next_symbol < symbols.len() && symbols[next_symbol].node as usize == idx {
+                let class = Class::from_symbol(input, &symbols[next_symbol]);
+                expr_of[idx] = simplified.class(class);
+                next_symbol += 1;
+                continue;
+            }
+
+            expr_of[idx] = match *node {
+                Node::Empty | Node::Position(_) => Self::EMPTY,
+                Node::Concat(r, s) => simplified.concat(expr_of[r as usize], expr_of[s as usize]),
+                Node::Alternate(r, s) => {
+                    simplified.alternate(expr_of[r as usize], expr_of[s as usize])
+                }
+                Node::Star(r) => simplified.star(expr_of[r as usize]),
+                Node::And(..) | Node::Not(..) => panic!("\"&\" and \"~\" can't be simplified"),
+            };
+        }
+        simplified.root = expr_of[syntax.root() as usize];
+
+        let mut pattern = Pattern {
+            bytes: [0; MAX_PATTERN_LEN],
+            len: 0,
+        };
+        simplified.print(&mut pattern);
+        simplified.pattern = pattern;
+        simplified
+    }
+
+    /// The simplified pattern. It has no flags of its own: what "(?i)" and "(?s)" did is spelled
+    /// out in its classes.
+    pub(crate) fn pattern(&self) -> &[u8] {
+        &self.pattern.bytes[..self.pattern.len]
+    }
+
+    fn print(&self, out: &mut Pattern) {
+        if self.anchors.start == Anchor::Line || self.anchors.end == Anchor::Line {
+            out.push("(?m)");
+        }
+        if self.anchors.start != Anchor::None {
+            out.push("^");
+        }
+        self.print_expr(self.root, false, out);
+        if self.anchors.end != Anchor::None {
+            out.push("$");
+        }
+    }
+
+    // `parenthesized`: whether an alternation has to be put in parentheses here, as it does
+    // inside a concatenation.
+    fn print_expr(&self, expr: ExprIdx, parenthesized: bool, out: &mut Pattern) {
+        match self.exprs[expr as usize] {
+            Expr::Empty => {}
+            Expr::Class(class) => self.classes[class as usize].print(out),
+            Expr::Concat(head, tail) => {
+                self.print_expr(head, true, out);
+                self.print_expr(tail, true, out);
+            }
+            Expr::Alternate(..) if parenthesized => {
+                out.push("(");
+                self.print_expr(expr, false, out);
+                out.push(")");
+            }
+            Expr::Alternate(head, rest) => {
+                self.print_expr(head, true, out);
+                out.push("|");
+                self.print_expr(rest, false, out);
+            }
+            Expr::Star(r) => {
+                if let Expr::Class(_) = self.exprs[r as usize] {
+                    self.print_expr(r, true, out);
+                } else {
+                    out.push("(");
+                    self.print_expr(r, false, out);
+                    out.push(")");
+                }
+                out.push("*");
+            }
+        }
+    }
+
+    fn class(&mut self, class: Class) -> ExprIdx {
+        let idx = self.add_class(class);
+        self.intern(Expr::Class(idx))
+    }
+
+    fn add_class(&mut self, class: Class) -> ClassIdx {
+        if let Some(idx) = self.classes[..self.class_count as usize]
+            .iter()
+            .position(|existing| *existing == class)
+        {
+            return idx as ClassIdx;
+        }
+
+        if self.class_count as usize >= MAX_CLASSES {
+            panic!("too many classes");
+        }
+        let idx = self.class_count;
+        self.class_count += 1;
+        self.classes[idx as usize] = class;
+        idx
+    }
+
+    // εr = rε = r, and (rs)t = r(st).
+    fn concat(&mut self, r: ExprIdx, s: ExprIdx) -> ExprIdx {
+        if r == Self::EMPTY {
+            return s;
+        }
+        if s == Self::EMPTY {
+            return r;
+        }
+        if let Expr::Concat(r1, r2) = self.exprs[r as usize] {
+            let tail = self.concat(r2, s);
+            return self.concat(r1, tail);
+        }
+        self.intern(Expr::Concat(r, s))
+    }
+
+    fn alternate(&mut self, r: ExprIdx, s: ExprIdx) -> ExprIdx {
+        self.alternation(&[r, s])
+    }
+
+    // Add the alternatives of `expr` to `alternatives[count..]`, and return the new count.
+    fn alternatives(
+        &self,
+        mut expr: ExprIdx,
+        alternatives: &mut [ExprIdx; MAX_ALTERNATIVES],
+        mut count: usize,
+    ) -> usize {
+        loop {
+            let (head, rest) = match self.exprs[expr as usize] {
+                Expr::Alternate(head, rest) => (head, Some(rest)),
+                _ => (expr, None),
+            };
+            if count == MAX_ALTERNATIVES {
+                panic!("too many alternatives");
+            }
+            alternatives[count] = head;
+            count += 1;
+            match rest {
+                Some(rest) => expr = rest,
+                None => return count,
+            }
+        }
+    }
+
+    // The alternation of `items`, in order, but for the rewrites: r|r = r, ε goes last, single
+    // characters and classes are merged into the first of them, and alternatives with the same
+    // first factor are factored.
+    fn alternation(&mut self, items: &[ExprIdx]) -> ExprIdx {
+        let mut flattened = [Self::EMPTY; MAX_ALTERNATIVES];
+        let mut flattened_count = 0;
+        for item in items {
+            flattened_count = self.alternatives(*item, &mut flattened, flattened_count);
+        }
+
+        let mut alternatives = [Self::EMPTY; MAX_ALTERNATIVES];
+        let mut count = 0;
+        let mut nullable = false;
+        let mut first_class = None;
+        for item in &flattened[..flattened_count] {
+            if *item == Self::EMPTY {
+                nullable = true;
+                continue;
+            }
+            if alternatives[..count].contains(item) {
+                continue;
+            }
+            if let Expr::Class(class) = self.exprs[*item as usize] {
+                match first_class {
+                    Some((at, into)) => {
+                        let union =
+                            self.classes[into as usize].union(&self.classes[class as usize]);
+                        if let Some(union) = union {
+                            let union = self.add_class(union);
+                            alternatives[at] = self.intern(Expr::Class(union));
+                            first_class = Some((at, union));
+                            continue;
+                        }
+                    }
+                    None => first_class = Some((count, class)),
+                }
+            }
+            alternatives[count] = *item;
+            count += 1;
+        }
+
+        // Common prefixes: ab|ac = a(b|c). Each alternative is its head (first factor) followed
+        // by its tail, which is ε if the alternative isn't a concatenation.
+        let mut factored = [Self::EMPTY; MAX_ALTERNATIVES];
+        let mut factored_count = 0;
+        let mut done = [false; MAX_ALTERNATIVES];
+        for idx in 0..count {
+            if done[idx] {
+                continue;
+            }
+            let head = self.head(alternatives[idx]);
+            let mut tails = [Self::EMPTY; MAX_ALTERNATIVES];
+            let mut tail_count = 0;
+            for other in idx..count {
+                if !done[other] && self.head(alternatives[other]) == head {
+                    done[other] = true;
+                    tails[tail_count] = self.tail(alternatives[other]);
+                    tail_count += 1;
+                }
+            }
+            factored[factored_count] = if tail_count == 1 {
+                alternatives[idx]
+            } else {
+                let rest = self.alternation(&tails[..tail_count]);
+                self.concat(head, rest)
+            };
+            factored_count += 1;
+        }
+
+        let mut result = if nullable { Some(Self::EMPTY) } else { None };
+        for alternative in factored[..factored_count].iter().rev() {
+            result = Some(match result {
+                None => *alternative,
+                Some(rest) => self.intern(Expr::Alternate(*alternative, rest)),
+            });
+        }
+        result.unwrap_or(Self::EMPTY)
+    }
+
+    fn head(&self, expr: ExprIdx) -> ExprIdx {
+        match self.exprs[expr as usize] {
+            Expr::Concat(head, _) => head,
+            _ => expr,
+        }
+    }
+
+    fn tail(&self, expr: ExprIdx) -> ExprIdx {
+        match self.exprs[expr as usize] {
+            Expr::Concat(_, tail) => tail,
+            _ => Self::EMPTY,
+        }
+    }
+
+    // ε* = ε, r** = r*, and (ε|r)* = r*.
+    fn star(&mut self, r: ExprIdx) -> ExprIdx {
+        match self.exprs[r as usize] {
+            Expr::Empty => Self::EMPTY,
+            Expr::Star(_) => r,
+            Expr::Alternate(..) => {
+                let mut alternatives = [Self::EMPTY; MAX_ALTERNATIVES];
+                let count = self.alternatives(r, &mut alternatives, 0);
+                if alternatives[count - 1] == Self::EMPTY {
+                    let inner = self.alternation(&alternatives[..count - 1]);
+                    return self.star(inner);
+                }
+                self.intern(Expr::Star(r))
+            }
+            _ => self.intern(Expr::Star(r)),
+        }
+    }
+
+    fn intern(&mut self, expr: Expr) -> ExprIdx {
+        if let Some(idx) = self.exprs[..self.expr_count as usize]
+            .iter()
+            .position(|existing| *existing == expr)
+        {
+            return idx as ExprIdx;
+        }
+
+        if self.expr_count as usize >= MAX_EXPRS {
+            panic!("too many expressions");
+        }
+        let idx = self.expr_count;
+        self.expr_count += 1;
+        self.exprs[idx as usize] = expr;
+        idx
+    }
+}
@@ -0,0 +1,256 @@
+use super::{Anchor, NFASize, Nfa, StateSet};
+use crate::io::{InputBuffer, Read};
+
+// Searching a stream that may be far too big to hold: bytes come in one at a time through
+// `Read`, and matches go out as soon as they are known.
+//
+// This is NFA simulation, as in the subset construction, but each state also remembers the
+// earliest offset that a path to it started at. A new path starts at every offset, so the
+// accepting state, when it is reached, knows where the leftmost match ending here began. Two
+// paths in the same state have the same future, so keeping only the earliest loses nothing.
+//
+// Once some match is found, paths that started after it are dropped, and the search goes on
+// only to see whether an earlier-starting or a longer match turns up. When no path is left,
+// the best match is reported and the input is backed up (with `ungetc`) to where it ended, to
+// look for the next one. The memory it takes doesn't depend on the input.
+//
+// Backing up is also the limit: the search can't look further past the best match than it can
+// back up, `MAX_LOOKAHEAD` bytes (a little under 2 KiB). If paths are still alive there, it
+// stops anyway and reports the match it has, marked `cut_short`; `Dfa::find` would have found
+// a longer one if one of those paths went on to accept. Otherwise the match is the
+// leftmost-longest one `Dfa::find` gives.
+
+// How far past the end of the best match so far the search looks for a longer one. Backing up
+// that far (plus the byte that found the end of the input) must fit in what `ungetc` can undo
+// in a 4 KiB `InputBuffer`.
+const MAX_LOOKAHEAD: usize = InputBuffer::<4096>::MAX_UNGETC - 2;
+
+// The start offset of a state that isn't in the set.
+const NO_START: u64 = u64::MAX;
+
+impl<const N: usize> Nfa<N> {
+    /// The leftmost-longest matches in `input`, found as the input is read, up to the
+    /// `MAX_LOOKAHEAD` limit. Matches don't overlap; after an empty match the search moves on a
+    /// byte.
+    pub(crate) fn stream_matches<'a, R: Read>(
+        &'a self,
+        input: &'a mut R,
+    ) -> StreamMatches<'a, N, R> {
+        StreamMatches {
+            nfa: self,
+            input,
+            offset: 0,
+            prev: None,
+            at_end: false,
+            done: false,
+        }
+    }
+}
+
+/// A match in a stream, as byte offsets from where the input was when the search began.
+#[derive(Clone, Copy)]
+pub(crate) struct StreamMatch {
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    /// Whether the search gave up looking for a longer match `MAX_LOOKAHEAD` bytes past `end`
+    /// while it still might have found one.
+    pub(crate) cut_short: bool,
+}
+
+pub(crate) struct StreamMatches<'a, const N: usize, R: Read> {
+    nfa: &'a Nfa<N>,
+    input: &'a mut R,
+    // How many bytes of the input we are past, and the last of them.
+    offset: u64,
+    prev: Option<u8>,
+    // Whether the last getc() found the end of the input (and so still needs undoing, if we
+    // back up).
+    at_end: bool,
+    done: bool,
+}
+
+impl<const N: usize, R: Read> Iterator for StreamMatches<'_, N, R> {
+    type Item = StreamMatch;
+
+    fn next(&mut self) -> Option<StreamMatch> {
+        if self.done {
+            return None;
+        }
+
+        let anchors = self.nfa.anchors;
+        let mut threads = Threads::<N>::new();
+        let mut best: Option<(u64, u64)> = None;
+        let mut cut_short = false;
+        loop {
+            if best.is_none() && self.can_start() {
+                threads.add(self.nfa, self.nfa.start_idx, self.offset);
+            }
+
+            let start = threads.start(self.nfa.accept_idx);
+            if start != NO_START && self.can_end(anchors.end) {
+                match best {
+                    Some((best_start, _)) if best_start < start => {}
+                    _ => best = Some((start, self.offset)),
+                }
+            }
+            if let Some((best_start, best_end)) = best {
+                threads = threads.started_by(best_start);
+                if threads.is_empty() {
+                    break;
+                }
+                if self.offset - best_end > MAX_LOOKAHEAD as u64 {
+                    cut_short = true;
+                    break;
+                }
+            } else if threads.is_empty() && anchors.start == Anchor::Text && self.offset > 0 {
+                // Nothing can start anywhere from here on.
+                self.done = true;
+                return None;
+            }
+
+            let Some(byte) = self.input.getc() else {
+                self.at_end = true;
+                break;
+            };
+            self.prev = Some(byte);
+            self.offset += 1;
+            threads = threads.step(self.nfa, byte);
+        }
+
+        let Some((start, end)) = best else {
+            self.done = true;
+            return None;
+        };
+        self.back_up_to(end);
+        if start == end {
+            match self.input.getc() {
+                Some(byte) => {
+                    self.prev = Some(byte);
+                    self.offset += 1;
+                }
+                None => self.done = true,
+            }
+        }
+        Some(StreamMatch {
+            start,
+            end,
+            cut_short,
+        })
+    }
+}
+
+impl<const N: usize, R: Read> StreamMatches<'_, N, R> {
+    fn can_start(&self) -> bool {
+        match self.nfa.anchors.start {
+            Anchor::None => true,
+            Anchor::Text => self.offset == 0,
+            Anchor::Line => matches!(self.prev, None | Some(b'\n')),
+        }
+    }
+
+    fn can_end(&mut self, end: Anchor) -> bool {
+        match end {
+            Anchor::None => true,
+            Anchor::Text => self.input.peek().is_none(),
+            Anchor::Line => matches!(self.input.peek(), None | Some(b'\n')),
+        }
+    }
+
+    // Unread the input back to `offset`, and re-read the byte before it for `prev`.
+    fn back_up_to(&mut self, offset: u64) {
+        let mut count = self.offset - offset;
+        if self.at_end {
+            count += 1;
+            self.at_end = false;
+        }
+        if offset > 0 {
+            count += 1;
+        }
+        for _ in 0..count {
+            self.input.ungetc();
+        }
+
+        self.offset = offset;
+        self.prev = if offset > 0 { self.input.getc() } else { None };
+    }
+}
+
+// The NFA states a search is in, each with the earliest offset that a path to it started at.
+#[derive(Clone, Copy)]
+struct Threads<const N: usize> {
+    set: StateSet,
+    starts: [u64; N],
+}
+
+impl<const N: usize> Threads<N> {
+    fn new() -> Self {
+        Self {
+            set: StateSet::new(),
+            starts: [NO_START; N],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    fn start(&self, state: NFASize) -> u64 {
+        self.starts[state as usize]
+    }
+
+    // Enter `state` on a path that started at `start`, and follow ε-transitions from it. A
+    // state already in the set is only entered again if this path started earlier, so no state
+    // is pushed twice.
+    fn add(&mut self, nfa: &Nfa<N>, state: NFASize, start: u64) {
+        if start >= self.starts[state as usize] {
+            return;
+        }
+        self.set.insert(state);
+        self.starts[state as usize] = start;
+
+        let mut stack = [0 as NFASize; N];
+        stack[0] = state;
+        let mut top = 1;
+        while top > 0 {
+            top -= 1;
+            let node = &nfa.states[stack[top] as usize];
+            for transition in &node.transitions[0..node.transition_count as usize] {
+                let to = transition.to_state_idx;
+                if transition.on_range.is_none() && start < self.starts[to as usize] {
+                    self.set.insert(to);
+                    self.starts[to as usize] = start;
+                    stack[top] = to;
+                    top += 1;
+                }
+            }
+        }
+    }
+
+    // Where the paths go on `byte`.
+    fn step(&self, nfa: &Nfa<N>, byte: u8) -> Self {
+        let mut next = Self::new();
+        for state in self.set.iter() {
+            let node = &nfa.states[state as usize];
+            for transition in &node.transitions[0..node.transition_count as usize] {
+                if let Some((lo, hi)) = transition.on_range {
+                    if lo <= byte && byte <= hi {
+                        next.add(nfa, transition.to_state_idx, self.starts[state as usize]);
+                    }
+                }
+            }
+        }
+        next
+    }
+
+    // Only the paths that started at `latest` or before.
+    fn started_by(&self, latest: u64) -> Self {
+        let mut kept = Self::new();
+        for state in self.set.iter() {
+            if self.starts[state as usize] <= latest {
+                kept.set.insert(state);
+                kept.starts[state as usize] = self.starts[state as usize];
+            }
+        }
+        kept
+    }
+}
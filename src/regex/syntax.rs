@@ -0,0 +1,774 @@
+use super::{utf8, Anchors, Flags, NFASize, StateSet, MAX_CLASS_RANGES};
+
+// The parser. Every construction starts from the syntax tree it builds: Thompson's construction
+// in regex.rs builds an NFA fragment for each node, children first; Glushkov's NFA and the
+// dragon book's direct DFA are worked out from nullable, first, last and follow, which are
+// defined over the tree; derivative.rs and simplify.rs rewrite it.
+//
+// The leaves are *positions*: the occurrences of symbols in the pattern, numbered from 1 in the
+// order they appear. The automata only see bytes, so a symbol is a set of bytes. "a" is one
+// position, and so is "(?i)a" (on "a" and "A") or "[a-z_]"; a character that takes several
+// bytes to encode is a chain of positions, one per byte, split the way utf8.rs splits it.
+//
+// Thompson's construction doesn't use the positions: it compiles each symbol whole, sharing
+// states between pieces that end the same way, so it can handle patterns with more positions
+// than an `NFASize` can count. Running out of positions is only an error for the constructions
+// that need them.
+//
+//     pattern   -> ("(?" modifiers ")")* "^"? expr "$"?
+//     expr      -> term rest
+//     rest      -> "|" expr
+//                | "(" flags expr ")" postfix
+//                | "(?" modifiers ")"
+//                | term
+//     flags     -> "?" modifiers ":"
+//                | ε
+//     modifiers -> [imsb]* ("-" [imsb]*)?
+//     term      -> "\\" <any> postfix
+//                | "." postfix
+//                | "[" class "]" postfix
+//                | 𝛼 in Σ postfix
+//                | ε
+//     class     -> item ("-" item)? class
+//                | item ("-" item)?
+//     item      -> "\\" <any>
+//                | 𝛼 in Σ
+//     postfix   -> "*"
+//                | ε
+//
+// Under (?b) there are three more rules. An NFA can't do "and" or "not", so only derivatives
+// can compile a tree that uses them:
+//
+//     rest      -> "&" sequence
+//     term      -> "~" term
+//                | "~" "(" flags expr ")" postfix
+//
+// where a sequence is an expr that stops before the first "|" or "&". So "~" binds tightest,
+// then concatenation, then "&", then "|".
+
+/// Positions are numbered from 1, and 0 is left for the state before any of them, so that the
+/// states of a Glushkov NFA can still be counted in an `NFASize`.
+pub(super) const MAX_POSITIONS: usize = NFASize::MAX as usize - 1;
+
+/// Enough for any pattern whose Thompson NFA fits in 256 states: "." alone is 53 nodes.
+pub(super) const MAX_NODES: usize = 2048;
+
+pub(super) type NodeIdx = u16;
+
+/// The bytes a position matches. A StateSet is a set of 256 values, which is all a byte can be.
+pub(super) type ByteSet = StateSet;
+
+#[derive(Clone, Copy)]
+pub(super) enum Node {
+    Empty,
+    Position(NFASize),
+    Concat(NodeIdx, NodeIdx),
+    Alternate(NodeIdx, NodeIdx),
+    Star(NodeIdx),
+    /// "r&s" under (?b).
+    And(NodeIdx, NodeIdx),
+    /// "~r" under (?b).
+    Not(NodeIdx),
+}
+
+/// Where a symbol came from: the pattern at `start` is a character, an escape, "." or a class,
+/// read under `flags`. It was compiled to the nodes `first..=node`, which hold nothing but its
+/// positions; `node` is the root of them.
+#[derive(Clone, Copy)]
+pub(super) struct Symbol {
+    pub(super) first: NodeIdx,
+    pub(super) node: NodeIdx,
+    pub(super) start: usize,
+    pub(super) flags: Flags,
+}
+
+pub(super) struct Syntax {
+    nodes: [Node; MAX_NODES],
+    node_count: NodeIdx,
+    // labels[p] for the positions 1..=position_count; labels[0] is never used.
+    labels: [ByteSet; MAX_POSITIONS + 1],
+    position_count: NFASize,
+    // Past MAX_POSITIONS, positions are all Position(0) and this is set.
+    too_many_positions: bool,
+    // Every symbol takes at least one position and two states of a Thompson NFA, so no pattern
+    // that any construction can compile has more symbols than there are positions.
+    symbols: [Symbol; MAX_POSITIONS],
+    symbol_count: NFASize,
+    // The tree for the part of the pattern parsed so far. Children always come before their
+    // parents in `nodes`, so the whole tree is the last node.
+    root: NodeIdx,
+    flags: Flags,
+    anchors: Anchors,
+}
+
+/// What a syntax tree says about its positions. For the tree of a regex r:
+///
+///     nullable   whether r matches ε
+///     first      the positions that can match the first byte of a match of r
+///     last       the positions that can match the last byte of a match of r
+///     follow(p)  the positions that can match the byte right after one matched by p
+pub(super) struct Positions {
+    pub(super) nullable: bool,
+    pub(super) first: StateSet,
+    pub(super) last: StateSet,
+    pub(super) follow: [StateSet; MAX_POSITIONS + 1],
+    pub(super) labels: [ByteSet; MAX_POSITIONS + 1],
+    pub(super) count: NFASize,
+    pub(super) anchors: Anchors,
+}
+
+impl Syntax {
+    pub(super) const fn from_regex_bytes(input: &[u8]) -> Self {
+        Self::from_regex_bytes_with_flags(input, Flags::NONE)
+    }
+
+    /// Parse `input` with `flags` turned on at its start.
+    pub(super) const fn from_regex_bytes_with_flags(input: &[u8], flags: Flags) -> Self {
+        let mut syntax = Self::new();
+        syntax.flags = flags;
+        syntax.parse(input);
+        syntax
+    }
+
+    const fn new() -> Self {
+        Self {
+            nodes: [Node::Empty; MAX_NODES],
+            node_count: 0,
+            labels: [StateSet::new(); MAX_POSITIONS + 1],
+            position_count: 0,
+            too_many_positions: false,
+            symbols: [Symbol {
+                first: 0,
+                node: 0,
+                start: 0,
+                flags: Flags::NONE,
+            }; MAX_POSITIONS],
+            symbol_count: 0,
+            root: 0,
+            flags: Flags::NONE,
+            anchors: Anchors::NONE,
+        }
+    }
+
+    // Unlike the NFA builders, the parser works on `&mut self`: a Syntax is big enough that a
+    // copy of it in every frame of the recursion would run out of stack on a few nested groups.
+    const fn parse(&mut self, input: &[u8]) {
+        let (flags, start_anchored, idx, body) = split_pattern(input, self.flags);
+        self.flags = flags;
+
+        if start_anchored {
+            self.anchors.start = Anchors::for_flags(self.flags);
+        }
+
+        if idx == body.len() {
+            self.add_node(Node::Empty);
+        } else if self.expr(body, idx) != body.len() {
+            panic!("unexpected character");
+        }
+
+        if body.len() != input.len() {
+            self.anchors.end = Anchors::for_flags(self.flags);
+        }
+    }
+
+    /// Add a position on no byte at all after the whole tree: the dragon book's endmarker "#".
+    /// It can never be matched, but it is in follow(p) exactly when p can end a match, so a set
+    /// of positions holding it stands for a point where the regex has matched. Returns the tree
+    /// and the endmarker's position.
+    pub(super) const fn augment(mut self) -> (Self, NFASize) {
+        let prev = self.root;
+        self.add_position(StateSet::new());
+        let end_marker = self.position_count;
+        self.concat(prev);
+        (self, end_marker)
+    }
+
+    const fn expr(&mut self, input: &[u8], mut idx: usize) -> usize {
+        idx = self.term(input, idx);
+        while idx < input.len() {
+            let last_idx = idx;
+
+            idx = self.rest(input, idx);
+            if last_idx == idx {
+                return idx;
+            }
+        }
+
+        input.len()
+    }
+
+    const fn term(&mut self, input: &[u8], mut idx: usize) -> usize {
+        if idx >= input.len() {
+            return idx;
+        }
+
+        idx = match input[idx] {
+            b'\\' | b'.' | b'[' | 0x80..=0xff => self.add_symbol(input, idx),
+
+            b'~' if self.flags.contains(Flags::SET_OPERATORS) => {
+                idx = if idx + 1 < input.len() && input[idx + 1] == b'(' {
+                    self.parenthesized(input, idx + 1)
+                } else {
+                    self.term(input, idx + 1)
+                };
+                let inner = self.root;
+                self.add_node(Node::Not(inner));
+                return idx;
+            }
+
+            b'&' if self.flags.contains(Flags::SET_OPERATORS) => {
+                self.add_node(Node::Empty);
+                return idx;
+            }
+
+            chara if is_literal(chara) => self.add_symbol(input, idx),
+
+            b'^' | b'$' => panic!("anchors are only supported at the start and end of a pattern"),
+
+            _ => {
+                self.add_node(Node::Empty);
+                return idx;
+            }
+        };
+
+        self.postfix(input, idx)
+    }
+
+    // Any number of "*"s: r** is (r*)*.
+    const fn postfix(&mut self, input: &[u8], mut idx: usize) -> usize {
+        while idx < input.len() && input[idx] == b'*' {
+            let inner = self.root;
+            self.add_node(Node::Star(inner));
+            idx += 1;
+        }
+
+        idx
+    }
+
+    // Like `expr`, but stops before "|" and, under (?b), "&": the operand of "&".
+    const fn sequence(&mut self, input: &[u8], mut idx: usize) -> usize {
+        idx = self.term(input, idx);
+        while idx < input.len() {
+            if input[idx] == b'|'
+                || (input[idx] == b'&' && self.flags.contains(Flags::SET_OPERATORS))
+            {
+                break;
+            }
+            let last_idx = idx;
+
+            idx = self.rest(input, idx);
+            if last_idx == idx {
+                break;
+            }
+        }
+
+        idx
+    }
+
+    const fn rest(&mut self, input: &[u8], mut idx: usize) -> usize {
+        let prev = self.root;
+        match input[idx] {
+            b'(' => self.group(input, idx),
+            b'&' if self.flags.contains(Flags::SET_OPERATORS) => {
+                idx = self.sequence(input, idx + 1);
+                let next = self.root;
+                self.add_node(Node::And(prev, next));
+                idx
+            }
+            b'|' => {
+                let node_count = self.node_count;
+                idx = self.expr(input, idx + 1);
+                // "s|" is s|ε.
+                if self.node_count == node_count {
+                    self.add_node(Node::Empty);
+                }
+                let next = self.root;
+                self.add_node(Node::Alternate(prev, next));
+                idx
+            }
+            _ => {
+                idx = self.term(input, idx);
+                self.concat(prev);
+                idx
+            }
+        }
+    }
+
+    const fn group(&mut self, input: &[u8], mut idx: usize) -> usize {
+        let prev = self.root;
+        idx = self.parenthesized(input, idx);
+        self.concat(prev);
+        idx
+    }
+
+    // Flags set inside a group are put back when the group ends. "(?flags)" sets flags for the
+    // rest of the enclosing group and matches ε; "(?flags:s)" is a group with its own flags.
+    // `idx` is at the "(".
+    const fn parenthesized(&mut self, input: &[u8], mut idx: usize) -> usize {
+        let outer_flags = self.flags;
+        idx += 1;
+
+        if idx < input.len() && input[idx] == b'?' {
+            let (on, off, end_idx) = flag_group(input, idx + 1);
+            self.flags = self.flags.union(on).difference(off);
+            if input[end_idx] == b')' {
+                self.add_node(Node::Empty);
+                return end_idx + 1;
+            }
+            idx = end_idx + 1;
+        }
+
+        idx = self.expr(input, idx);
+
+        if idx >= input.len() {
+            panic!("unexpected end of stream: unterminated group, expected ')'");
+        }
+
+        if b')' == input[idx] {
+            self.flags = outer_flags;
+            return self.postfix(input, idx + 1);
+        }
+
+        panic!("unterminated group, expected ')'");
+    }
+
+    // The symbol at `input[idx]`, as a subtree of positions; returns the index just past it.
+    const fn add_symbol(&mut self, input: &[u8], idx: usize) -> usize {
+        let first = self.node_count;
+        let (ranges, count, end_idx) = read_symbol(input, idx, self.flags);
+        self.add_scalar_ranges_term(&ranges, count);
+
+        if self.symbol_count as usize >= MAX_POSITIONS {
+            panic!("too many symbols");
+        }
+        self.symbols[self.symbol_count as usize] = Symbol {
+            first,
+            node: self.root,
+            start: idx,
+            flags: self.flags,
+        };
+        self.symbol_count += 1;
+        end_idx
+    }
+
+    // The alternation of the encodings of the scalar values in `ranges[0..count]`. Every one-byte
+    // encoding goes into a single position, first; each longer piece is a chain of positions,
+    // one per byte. Under (?i), ASCII letters in a range bring their other case along.
+    const fn add_scalar_ranges_term(&mut self, ranges: &[(u32, u32)], count: usize) {
+        let mut ascii = StateSet::new();
+        let mut idx = 0;
+        while idx < count {
+            let (lo, hi) = ranges[idx];
+            if lo > hi || hi > utf8::MAX_SCALAR {
+                panic!("invalid character range");
+            }
+            ascii = insert_ascii(ascii, lo, hi);
+            if self.flags.contains(Flags::CASE_INSENSITIVE) {
+                let other = utf8::other_cases(lo, hi);
+                ascii = insert_ascii(ascii, other[0].0, other[0].1);
+                ascii = insert_ascii(ascii, other[1].0, other[1].1);
+            }
+            idx += 1;
+        }
+
+        let mut alternatives = 0;
+        if !ascii.is_empty() {
+            self.add_position(ascii);
+            alternatives += 1;
+        }
+
+        idx = 0;
+        while idx < count {
+            let (mut lo, hi) = ranges[idx];
+            if lo <= utf8::MAX_BY_LENGTH[0] {
+                lo = utf8::MAX_BY_LENGTH[0] + 1;
+            }
+            while let Some((piece_lo, piece_hi)) = utf8::first_piece(lo, hi) {
+                let prev = self.root;
+                self.add_sequence(piece_lo, piece_hi);
+                if alternatives > 0 {
+                    let next = self.root;
+                    self.add_node(Node::Alternate(prev, next));
+                }
+                alternatives += 1;
+                lo = piece_hi + 1;
+            }
+            idx += 1;
+        }
+    }
+
+    // The chain of positions [lo[0]-hi[0]] [lo[1]-hi[1]] ... for one piece of a range.
+    const fn add_sequence(&mut self, lo: u32, hi: u32) {
+        let (lo_bytes, len) = utf8::encode(lo);
+        let (hi_bytes, _) = utf8::encode(hi);
+        let mut k = 0;
+        while k < len {
+            let prev = self.root;
+            let mut label = StateSet::new();
+            let mut byte = lo_bytes[k];
+            loop {
+                label.insert(byte);
+                if byte == hi_bytes[k] {
+                    break;
+                }
+                byte += 1;
+            }
+            self.add_position(label);
+            if k > 0 {
+                self.concat(prev);
+            }
+            k += 1;
+        }
+    }
+
+    const fn add_position(&mut self, label: ByteSet) {
+        if self.position_count as usize >= MAX_POSITIONS {
+            self.too_many_positions = true;
+            self.add_node(Node::Position(0));
+            return;
+        }
+        self.position_count += 1;
+        self.labels[self.position_count as usize] = label;
+        self.add_node(Node::Position(self.position_count));
+    }
+
+    // "st", with s at `prev` and t the latest node.
+    const fn concat(&mut self, prev: NodeIdx) {
+        let next = self.root;
+        self.add_node(Node::Concat(prev, next));
+    }
+
+    const fn add_node(&mut self, node: Node) {
+        if self.node_count as usize >= MAX_NODES {
+            panic!("too many syntax tree nodes");
+        }
+        self.nodes[self.node_count as usize] = node;
+        self.root = self.node_count;
+        self.node_count += 1;
+    }
+
+    /// Every node, children before parents.
+    pub(super) const fn nodes(&self) -> &[Node] {
+        self.nodes.split_at(self.node_count as usize).0
+    }
+
+    /// Every symbol, in the order they appear in the pattern (which is also the order of their
+    /// nodes).
+    pub(super) const fn symbols(&self) -> &[Symbol] {
+        self.symbols.split_at(self.symbol_count as usize).0
+    }
+
+    pub(super) const fn root(&self) -> NodeIdx {
+        self.root
+    }
+
+    pub(super) fn label(&self, position: NFASize) -> ByteSet {
+        self.check_positions();
+        self.labels[position as usize]
+    }
+
+    pub(super) const fn anchors(&self) -> Anchors {
+        self.anchors
+    }
+
+    /// Whether there is an "&" or a "~" under (?b) anywhere in the pattern.
+    pub(super) fn has_set_operators(&self) -> bool {
+        self.nodes()
+            .iter()
+            .any(|node| matches!(node, Node::And(..) | Node::Not(..)))
+    }
+
+    /// Work out nullable, first and last for every node, bottom up, and collect follow along the
+    /// way. Only two rules put anything in follow:
+    ///
+    ///     st:  every position in first(t) can follow every position in last(s)
+    ///     s*:  every position in first(s) can follow every position in last(s)
+    pub(super) fn positions(&self) -> Positions {
+        self.check_positions();
+        let mut nullable = [false; MAX_NODES];
+        let mut first = [StateSet::new(); MAX_NODES];
+        let mut last = [StateSet::new(); MAX_NODES];
+        let mut follow = [StateSet::new(); MAX_POSITIONS + 1];
+
+        for idx in 0..self.node_count as usize {
+            match self.nodes[idx] {
+                Node::Empty => nullable[idx] = true,
+                Node::Position(p) => {
+                    first[idx].insert(p);
+                    last[idx].insert(p);
+                }
+                Node::Alternate(s, t) => {
+                    let (s, t) = (s as usize, t as usize);
+                    nullable[idx] = nullable[s] || nullable[t];
+                    first[idx] = first[s].union(first[t]);
+                    last[idx] = last[s].union(last[t]);
+                }
+                Node::Concat(s, t) => {
+                    let (s, t) = (s as usize, t as usize);
+                    nullable[idx] = nullable[s] && nullable[t];
+                    first[idx] = first[s];
+                    if nullable[s] {
+                        first[idx] = first[idx].union(first[t]);
+                    }
+                    last[idx] = last[t];
+                    if nullable[t] {
+                        last[idx] = last[idx].union(last[s]);
+                    }
+                    for p in last[s].iter() {
+                        follow[p as usize] = follow[p as usize].union(first[t]);
+                    }
+                }
+                Node::Star(s) => {
+                    let s = s as usize;
+                    nullable[idx] = true;
+                    first[idx] = first[s];
+                    last[idx] = last[s];
+                    for p in last[s].iter() {
+                        follow[p as usize] = follow[p as usize].union(first[s]);
+                    }
+                }
+                Node::And(..) | Node::Not(..) => {
+                    panic!("\"&\" and \"~\" can only be compiled through derivatives")
+                }
+            }
+        }
+
+        let root = self.root as usize;
+        Positions {
+            nullable: nullable[root],
+            first: first[root],
+            last: last[root],
+            follow,
+            labels: self.labels,
+            count: self.position_count,
+            anchors: self.anchors,
+        }
+    }
+
+    fn check_positions(&self) {
+        if self.too_many_positions {
+            panic!("too many positions");
+        }
+    }
+}
+
+// Add the one-byte encodings in lo..=hi (that is, the ASCII part of it) to `set`.
+const fn insert_ascii(mut set: ByteSet, lo: u32, hi: u32) -> ByteSet {
+    let mut chara = lo;
+    while chara <= hi && chara <= utf8::MAX_BY_LENGTH[0] {
+        set.insert(chara as u8);
+        chara += 1;
+    }
+    set
+}
+
+/// What the symbol at `input[idx]` matches -- a character, an escape, "." or a class, read
+/// under `flags` -- as scalar value ranges[0..count], and the index just past it. Case folding
+/// under (?i) is left to whatever compiles the ranges.
+pub(super) const fn read_symbol(
+    input: &[u8],
+    idx: usize,
+    flags: Flags,
+) -> ([(u32, u32); MAX_CLASS_RANGES], usize, usize) {
+    let mut ranges = [(0, 0); MAX_CLASS_RANGES];
+    match input[idx] {
+        b'\\' => {
+            let (chara, end_idx) = escaped(input, idx + 1);
+            ranges[0] = (chara as u32, chara as u32);
+            (ranges, 1, end_idx)
+        }
+        // "." is the class of every character but "\n" -- or of every character, under (?s).
+        b'.' if flags.contains(Flags::DOT_MATCHES_NEWLINE) => {
+            ranges[0] = (0, utf8::MAX_SCALAR);
+            (ranges, 1, idx + 1)
+        }
+        b'.' => {
+            let newline = b'\n' as u32;
+            ranges[0] = (0, newline - 1);
+            ranges[1] = (newline + 1, utf8::MAX_SCALAR);
+            (ranges, 2, idx + 1)
+        }
+        b'[' => class(input, idx + 1),
+        _ => {
+            let (scalar, end_idx) = utf8::decode(input, idx);
+            ranges[0] = (scalar, scalar);
+            (ranges, 1, end_idx)
+        }
+    }
+}
+
+/// Split a pattern into the flag groups and "^" it starts with, its body, and the "$" it ends
+/// with. Returns the flags in effect at the start of the body (`flags`, plus what the leading
+/// groups say), whether there is a "^", the index the body starts at, and the pattern with any
+/// trailing "$" cut off.
+const fn split_pattern(input: &[u8], mut flags: Flags) -> (Flags, bool, usize, &[u8]) {
+    let mut idx = 0;
+
+    // Flags may come before a leading "^", as in "(?m)^abc$".
+    while idx + 1 < input.len() && input[idx] == b'(' && input[idx + 1] == b'?' {
+        let (on, off, end_idx) = flag_group(input, idx + 2);
+        if input[end_idx] != b')' {
+            break;
+        }
+        flags = flags.union(on).difference(off);
+        idx = end_idx + 1;
+    }
+
+    let start_anchored = idx < input.len() && input[idx] == b'^';
+    if start_anchored {
+        idx += 1;
+    }
+
+    // A trailing "$" is an anchor unless it's escaped -- that is, unless it follows an odd
+    // number of backslashes.
+    let mut body = input;
+    if input.len() > idx && input[input.len() - 1] == b'$' {
+        let mut backslashes = 0;
+        while backslashes + 1 < input.len() - idx && input[input.len() - 2 - backslashes] == b'\\' {
+            backslashes += 1;
+        }
+        if backslashes % 2 == 0 {
+            body = input.split_at(input.len() - 1).0;
+        }
+    }
+
+    (flags, start_anchored, idx, body)
+}
+
+// Parse the inside of "(?flags)" or "(?flags:", starting right after the "?": any of "i",
+// "m", "s" and "b", optionally followed by "-" and flags to turn off. Returns the flags to turn
+// on, the flags to turn off and the index of the closing ")" or ":".
+const fn flag_group(input: &[u8], mut idx: usize) -> (Flags, Flags, usize) {
+    let mut on = Flags::NONE;
+    let mut off = Flags::NONE;
+    let mut negated = false;
+    while idx < input.len() {
+        let flag = match input[idx] {
+            b')' | b':' => return (on, off, idx),
+            b'-' if !negated => {
+                negated = true;
+                idx += 1;
+                continue;
+            }
+            b'i' => Flags::CASE_INSENSITIVE,
+            b's' => Flags::DOT_MATCHES_NEWLINE,
+            b'm' => Flags::MULTILINE,
+            b'b' => Flags::SET_OPERATORS,
+            _ => panic!("unknown inline flag"),
+        };
+        if negated {
+            off = off.union(flag);
+        } else {
+            on = on.union(flag);
+        }
+        idx += 1;
+    }
+
+    panic!("unexpected end of input: unterminated flag group, expected ')' or ':'");
+}
+
+// Whether `chara` stands for itself in a pattern, outside of a class.
+pub(super) const fn is_literal(chara: u8) -> bool {
+    matches!(
+        chara,
+        b'a'..=b'z'
+        | b'A'..=b'Z'
+        | b'0'..=b'9'
+        | b'!'
+        | b'@'
+        | b'#'
+        | b'%'
+        | b'&'
+        | b'-'
+        | b'='
+        | b'+'
+        | b';'
+        | b':'
+        | b'"'
+        | b','
+        | b'<'
+        | b'>'
+        | b'/'
+        | b'`'
+        | b'~'
+        | b' '
+        | b'\''
+    )
+}
+
+// The character escaped by the "\\" just before `input[idx]`, and the index after it.
+const fn escaped(input: &[u8], idx: usize) -> (u8, usize) {
+    if idx >= input.len() {
+        panic!("unexpected end of input: expected escaped character");
+    }
+
+    match input[idx] {
+        b'n' => (b'\n', idx + 1),
+        b't' => (b'\t', idx + 1),
+
+        chara @ (b'$' | b'^' | b'(' | b')' | b'{' | b'}' | b'[' | b']' | b'|' | b'?' | b'*'
+        | b'.' | b'\\') => (chara, idx + 1),
+
+        _ => panic!("unexpected escaped character value"),
+    }
+}
+
+// The inside of a bracketed class: the characters and ranges of characters it lists, which may
+// be any Unicode scalar values, as ranges[0..count], and the index just past the "]". `idx` is
+// just past the "[".
+const fn class(input: &[u8], mut idx: usize) -> ([(u32, u32); MAX_CLASS_RANGES], usize, usize) {
+    let mut ranges = [(0u32, 0u32); MAX_CLASS_RANGES];
+    let mut count = 0;
+
+    if idx < input.len() && input[idx] == b'^' {
+        panic!("negated classes are not supported");
+    }
+
+    loop {
+        if idx >= input.len() {
+            panic!("unexpected end of input: unterminated class, expected ']'");
+        }
+        if input[idx] == b']' {
+            if count == 0 {
+                panic!("empty class");
+            }
+            break;
+        }
+
+        let lo;
+        (lo, idx) = class_item(input, idx);
+        let mut hi = lo;
+        // A "-" right before the "]" is just a "-".
+        if idx + 1 < input.len() && input[idx] == b'-' && input[idx + 1] != b']' {
+            (hi, idx) = class_item(input, idx + 1);
+            if hi < lo {
+                panic!("class range out of order");
+            }
+        }
+
+        if count == MAX_CLASS_RANGES {
+            panic!("too many ranges in class");
+        }
+        ranges[count] = (lo, hi);
+        count += 1;
+    }
+
+    (ranges, count, idx + 1)
+}
+
+// One character of a class, as a scalar value, and the index after it.
+const fn class_item(input: &[u8], idx: usize) -> (u32, usize) {
+    if input[idx] != b'\\' {
+        return utf8::decode(input, idx);
+    }
+    if idx + 1 >= input.len() {
+        panic!("unexpected end of input: expected escaped character");
+    }
+
+    match input[idx + 1] {
+        b'n' => (b'\n' as u32, idx + 2),
+        b't' => (b'\t' as u32, idx + 2),
+        chara @ (b'[' | b']' | b'^' | b'-' | b'\\') => (chara as u32, idx + 2),
+        _ => panic!("unexpected escaped character value in class"),
+    }
+}
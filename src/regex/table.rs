@@ -0,0 +1,234 @@
+use super::{ByteClasses, Dfa, Nfa, StateSet};
+use crate::io::{itoa, puts};
+
+// Transition tables, as the dragon book draws them: one row per state, one column per input
+// symbol (plus ε for an NFA). The start state is marked with "→" and accepting states with "*".
+// The input symbols are byte classes, so `.` takes up a few columns rather than 255 of them;
+// classes that no state has a transition on are left out.
+//
+//           | a   | b   | ε
+//     ------+-----+-----+------
+//     →  0  | {1} | -   | -
+//        1  | -   | -   | {2,5}
+//      * 5  | -   | -   | -
+
+impl<const N: usize> Nfa<N> {
+    pub(crate) fn print_table(&self) {
+        let classes = ByteClasses::from_nfa(self);
+        let mut symbols = [(0u8, 0u8); 256];
+        let mut symbol_count = 0;
+        for class in 0..classes.count() {
+            let range = classes.range(class as u8);
+            if self.transitions().any(|(_, on, _)| covers(on, Some(range))) {
+                symbols[symbol_count] = range;
+                symbol_count += 1;
+            }
+        }
+        let has_epsilon = self.transitions().any(|(_, on, _)| on.is_none());
+
+        let columns = symbol_count + has_epsilon as usize;
+        print_grid(self.state_count as usize, columns, |row, column| {
+            let mut cell = Cell::new();
+            let state = row as u8;
+            // Any column past the last symbol is the ε column.
+            let on = column
+                .checked_sub(1)
+                .and_then(|column| symbols[0..symbol_count].get(column))
+                .copied();
+            match (row, column) {
+                (HEADER, STATE) => {}
+                (HEADER, _) => match on {
+                    Some(range) => cell.range(range),
+                    None => cell.text("ε"),
+                },
+                (_, STATE) => cell.state(state, state == self.start_idx, state == self.accept_idx),
+                _ => {
+                    let mut targets = StateSet::new();
+                    for (from, transition_on, to) in self.transitions() {
+                        if from == state && covers(transition_on, on) {
+                            targets.insert(to);
+                        }
+                    }
+                    cell.set(&targets);
+                }
+            }
+            cell
+        });
+    }
+}
+
+impl<const N: usize, const C: usize> Dfa<N, C> {
+    pub(crate) fn print_table(&self) {
+        let mut symbols = [0u8; 256];
+        let mut symbol_count = 0;
+        for class in 0..self.classes.count() {
+            if (1..self.state_count).any(|state| self.transition(state, class) != Self::DEAD) {
+                symbols[symbol_count] = class as u8;
+                symbol_count += 1;
+            }
+        }
+
+        // The dead state gets no row; a "-" means "goes to the dead state".
+        print_grid(
+            self.state_count as usize - 1,
+            symbol_count,
+            |row, column| {
+                let mut cell = Cell::new();
+                let state = row.wrapping_add(1) as u8;
+                match (row, column) {
+                    (HEADER, STATE) => {}
+                    (HEADER, _) => cell.range(self.classes.range(symbols[column - 1])),
+                    (_, STATE) => {
+                        cell.state(state, state == self.start_idx, self.is_accepting(state))
+                    }
+                    _ => match self.transition(state, symbols[column - 1] as usize) {
+                        Self::DEAD => cell.text("-"),
+                        target => cell.number(target as u32),
+                    },
+                }
+                cell
+            },
+        );
+    }
+}
+
+// Whether an NFA transition on `on_range` belongs in the column for the byte class `symbol` (None
+// for ε). A class is never split by a transition, so it's enough to look at one byte of it.
+fn covers(on_range: Option<(u8, u8)>, symbol: Option<(u8, u8)>) -> bool {
+    match (on_range, symbol) {
+        (Some((lo, hi)), Some((chara, _))) => lo <= chara && chara <= hi,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+const HEADER: usize = usize::MAX;
+const STATE: usize = 0;
+
+// Print a table with a header row and a state column. `cell` is called with row `HEADER` for the
+// header and column `STATE` for the state column; data rows count from 0 and data columns from 1.
+// Every cell is rendered twice: once to measure the columns, once to print them.
+fn print_grid(rows: usize, columns: usize, cell: impl Fn(usize, usize) -> Cell) {
+    let mut widths = [0usize; 258];
+    let each_row = || core::iter::once(HEADER).chain(0..rows);
+    for row in each_row() {
+        for (column, width) in widths[0..=columns].iter_mut().enumerate() {
+            *width = (*width).max(cell(row, column).width());
+        }
+    }
+
+    for row in each_row() {
+        for (column, width) in widths[0..=columns].iter().enumerate() {
+            let rendered = cell(row, column);
+            if column > 0 {
+                puts(" | ");
+            }
+            puts(rendered.as_bytes());
+            if column < columns {
+                for _ in rendered.width()..*width {
+                    puts(" ");
+                }
+            }
+        }
+        puts("\n");
+
+        if row == HEADER {
+            for (column, width) in widths[0..=columns].iter().enumerate() {
+                if column > 0 {
+                    puts("-+-");
+                }
+                for _ in 0..*width {
+                    puts("-");
+                }
+            }
+            puts("\n");
+        }
+    }
+}
+
+// A table cell, rendered into a buffer so that it can be measured before it is printed.
+struct Cell {
+    buf: [u8; 64],
+    len: usize,
+}
+
+impl Cell {
+    fn new() -> Self {
+        Self {
+            buf: [0; 64],
+            len: 0,
+        }
+    }
+
+    fn text<T: AsRef<[u8]>>(&mut self, text: T) {
+        for byte in text.as_ref() {
+            if self.len == self.buf.len() {
+                // Out of room; mark the cell as truncated rather than fail.
+                self.buf[self.len - 3..].copy_from_slice(b"...");
+                return;
+            }
+            self.buf[self.len] = *byte;
+            self.len += 1;
+        }
+    }
+
+    fn number(&mut self, number: u32) {
+        self.text(itoa(number));
+    }
+
+    fn symbol(&mut self, chara: u8) {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        match chara {
+            b'\n' => self.text("\\n"),
+            b'\t' => self.text("\\t"),
+            b' ' => self.text("' '"),
+            b'!'..=b'~' => self.text([chara]),
+            _ => {
+                self.text("\\x");
+                self.text([HEX[(chara >> 4) as usize], HEX[(chara & 0xf) as usize]]);
+            }
+        }
+    }
+
+    fn range(&mut self, (lo, hi): (u8, u8)) {
+        self.symbol(lo);
+        if hi != lo {
+            self.text("-");
+            self.symbol(hi);
+        }
+    }
+
+    fn state(&mut self, state: u8, is_start: bool, is_accepting: bool) {
+        self.text(if is_start { "→" } else { " " });
+        self.text(if is_accepting { "* " } else { "  " });
+        self.number(state as u32);
+    }
+
+    fn set(&mut self, set: &StateSet) {
+        if set.is_empty() {
+            self.text("-");
+            return;
+        }
+
+        self.text("{");
+        for (nth, idx) in set.iter().enumerate() {
+            if nth > 0 {
+                self.text(",");
+            }
+            self.number(idx as u32);
+        }
+        self.text("}");
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[0..self.len]
+    }
+
+    // The width in columns: count everything but UTF-8 continuation bytes.
+    fn width(&self) -> usize {
+        self.as_bytes()
+            .iter()
+            .filter(|byte| *byte & 0xc0 != 0x80)
+            .count()
+    }
+}
@@ -0,0 +1,45 @@
+use super::{r#move, ε_closure, Nfa, StateSet};
+use crate::dot;
+
+impl<const N: usize> Nfa<N> {
+    /// Run the NFA on `input` the way the subset construction would, one set of states at a
+    /// time, and draw each step as its own DOT digraph: the automaton as `debug_print` lays it
+    /// out, with the states the NFA is in filled in and the input read so far underneath. The
+    /// first graph is before any input, at ε_closure({start}); then there is one per byte,
+    /// until the input runs out or no state is left. Returns whether the NFA accepts `input`.
+    ///
+    /// `dot -Tpng -O` turns the graphs into numbered images, one frame each.
+    pub(crate) fn trace(&self, input: &[u8]) -> bool {
+        let mut set = StateSet::new();
+        set.insert(self.start_idx);
+        set = ε_closure(self, set);
+        self.draw_frame(input, 0, &set);
+
+        for (idx, chara) in input.iter().enumerate() {
+            set = ε_closure(self, r#move(self, &set, *chara));
+            self.draw_frame(input, idx + 1, &set);
+            if set.is_empty() {
+                return false;
+            }
+        }
+        set.contains(self.accept_idx)
+    }
+
+    fn draw_frame(&self, input: &[u8], read: usize, set: &StateSet) {
+        dot::begin_digraph("LR");
+        self.draw(b"nfa", set);
+        dot::graph_label(|label| {
+            label.text("read \"");
+            for chara in &input[0..read] {
+                label.byte(*chara);
+            }
+            label.text("\"");
+            if set.is_empty() {
+                label.text(": stuck");
+            } else if set.contains(self.accept_idx) {
+                label.text(": accepting");
+            }
+        });
+        dot::end_digraph();
+    }
+}
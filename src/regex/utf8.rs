@@ -0,0 +1,308 @@
+use super::{Flags, NFASize, Nfa, TRANSITIONS_PER_STATE};
+
+// Patterns are UTF-8, but the automata only ever see bytes. A character class over Unicode
+// scalar values is compiled into the set of byte sequences that encode them, so that the NFA
+// (and every DFA built from it) matches exactly the valid UTF-8 encodings of the class and
+// nothing else -- not a stray continuation byte, not an overlong encoding, not a surrogate.
+//
+// For example, [α-ω] (U+03B1 to U+03C9) becomes
+//
+//     \xce [\xb1-\xbf]
+//     \xcf [\x80-\x89]
+//
+// The splitting is the one RE2 uses: cut the range wherever the encoded length changes, then
+// keep cutting until each piece is a plain sequence of byte ranges, one per byte.
+
+pub(super) const MAX_SCALAR: u32 = 0x10ffff;
+
+const SURROGATES: (u32, u32) = (0xd800, 0xdfff);
+
+// The largest scalar value that encodes to 1, 2 and 3 bytes.
+pub(super) const MAX_BY_LENGTH: [u32; 3] = [0x7f, 0x7ff, 0xffff];
+
+/// Decode the UTF-8 encoded scalar value starting at `input[idx]`; returns it and the index
+/// just past it. Patterns must be valid UTF-8.
+pub(super) const fn decode(input: &[u8], idx: usize) -> (u32, usize) {
+    let lead = input[idx];
+    let (len, mut scalar, min) = match lead {
+        0x00..=0x7f => return (lead as u32, idx + 1),
+        0xc2..=0xdf => (2, (lead & 0x1f) as u32, 0x80),
+        0xe0..=0xef => (3, (lead & 0x0f) as u32, 0x800),
+        0xf0..=0xf4 => (4, (lead & 0x07) as u32, 0x10000),
+        _ => panic!("invalid UTF-8 in pattern: unexpected byte"),
+    };
+    if idx + len > input.len() {
+        panic!("invalid UTF-8 in pattern: truncated sequence");
+    }
+
+    let mut k = 1;
+    while k < len {
+        let byte = input[idx + k];
+        if byte & 0xc0 != 0x80 {
+            panic!("invalid UTF-8 in pattern: expected a continuation byte");
+        }
+        scalar = scalar << 6 | (byte & 0x3f) as u32;
+        k += 1;
+    }
+
+    if scalar < min || scalar > MAX_SCALAR || (SURROGATES.0 <= scalar && scalar <= SURROGATES.1) {
+        panic!("invalid UTF-8 in pattern: not a scalar value");
+    }
+    (scalar, idx + len)
+}
+
+// The UTF-8 encoding of `scalar`, and how many bytes of it there are.
+pub(super) const fn encode(scalar: u32) -> ([u8; 4], usize) {
+    if scalar <= MAX_BY_LENGTH[0] {
+        ([scalar as u8, 0, 0, 0], 1)
+    } else if scalar <= MAX_BY_LENGTH[1] {
+        (
+            [0xc0 | (scalar >> 6) as u8, continuation(scalar, 0), 0, 0],
+            2,
+        )
+    } else if scalar <= MAX_BY_LENGTH[2] {
+        let lead = 0xe0 | (scalar >> 12) as u8;
+        (
+            [lead, continuation(scalar, 6), continuation(scalar, 0), 0],
+            3,
+        )
+    } else {
+        let lead = 0xf0 | (scalar >> 18) as u8;
+        (
+            [
+                lead,
+                continuation(scalar, 12),
+                continuation(scalar, 6),
+                continuation(scalar, 0),
+            ],
+            4,
+        )
+    }
+}
+
+// The continuation byte carrying the six bits of `scalar` starting at bit `shift`.
+const fn continuation(scalar: u32, shift: u32) -> u8 {
+    0x80 | (scalar >> shift & 0x3f) as u8
+}
+
+// How many shared suffix states one character class can keep track of. Past that, pieces get
+// fresh states of their own, which is only bigger, never wrong.
+const MAX_SUFFIXES: usize = 32;
+
+// The states already built for one character class that do nothing but read one byte range and
+// go on to another state: (lo, hi, to) for each, and the state. A piece that needs the same
+// state reuses it instead of building its own, so every piece that ends in, say, [\x80-\xbf]
+// shares the one state that reads it -- what RE2 calls the suffix cache.
+struct Suffixes {
+    entries: [(u8, u8, NFASize, NFASize); MAX_SUFFIXES],
+    count: usize,
+}
+
+impl<const N: usize> Nfa<N> {
+    // Rule 2 for a set of characters: one path from i to f for each byte sequence that encodes a
+    // scalar value in one of `ranges[0..count]`. Paths that end the same way share their tails.
+    //
+    // Paths leave i (and the states chained after it) at most TRANSITIONS_PER_STATE - 1 at a time;
+    // the last slot links to the next state on ε when there are more.
+    //
+    // Under (?i), ASCII letters in a range bring their other case along.
+    pub(super) const fn add_scalar_ranges_term(
+        mut self,
+        ranges: &[(u32, u32)],
+        count: usize,
+    ) -> Self {
+        let i_idx;
+        let f_idx;
+        (self, i_idx) = self.add_state();
+        (self, f_idx) = self.add_state();
+
+        let mut suffixes = Suffixes {
+            entries: [(0, 0, 0, 0); MAX_SUFFIXES],
+            count: 0,
+        };
+        let mut branch = i_idx;
+        let mut idx = 0;
+        while idx < count {
+            let (lo, hi) = ranges[idx];
+            if lo > hi || hi > MAX_SCALAR {
+                panic!("invalid character range");
+            }
+            (self, branch) = self.add_utf8_range(&mut suffixes, branch, lo, hi, f_idx);
+
+            if self.flags.contains(Flags::CASE_INSENSITIVE) {
+                let other = other_cases(lo, hi);
+                (self, branch) =
+                    self.add_utf8_range(&mut suffixes, branch, other[0].0, other[0].1, f_idx);
+                (self, branch) =
+                    self.add_utf8_range(&mut suffixes, branch, other[1].0, other[1].1, f_idx);
+            }
+            idx += 1;
+        }
+
+        self.start_idx = i_idx;
+        self.accept_idx = f_idx;
+        self
+    }
+
+    // Add paths from `branch` to `f_idx` for the encodings of lo..=hi, and return the state that
+    // later paths should leave from. An empty range (lo > hi) adds nothing.
+    const fn add_utf8_range(
+        mut self,
+        suffixes: &mut Suffixes,
+        mut branch: NFASize,
+        mut lo: u32,
+        hi: u32,
+        f_idx: NFASize,
+    ) -> (Self, NFASize) {
+        while let Some((piece_lo, piece_hi)) = first_piece(lo, hi) {
+            let (lo_bytes, len) = encode(piece_lo);
+            let (hi_bytes, _) = encode(piece_hi);
+            (self, branch) = self.add_sequence(suffixes, branch, lo_bytes, hi_bytes, len, f_idx);
+            lo = piece_hi + 1;
+        }
+        (self, branch)
+    }
+
+    // One path from `branch` to `f_idx` on [lo[0]-hi[0]] [lo[1]-hi[1]] ... Built back to front,
+    // so that each state after the first byte can come from `suffixes`.
+    const fn add_sequence(
+        mut self,
+        suffixes: &mut Suffixes,
+        mut branch: NFASize,
+        lo: [u8; 4],
+        hi: [u8; 4],
+        len: usize,
+        f_idx: NFASize,
+    ) -> (Self, NFASize) {
+        if self.states[branch as usize].transition_count as usize == TRANSITIONS_PER_STATE - 1 {
+            let next_idx;
+            (self, next_idx) = self.add_state();
+            self.states[branch as usize] =
+                self.states[branch as usize].add_transition(None, next_idx);
+            branch = next_idx;
+        }
+
+        let mut to_idx = f_idx;
+        let mut k = len - 1;
+        while k > 0 {
+            (self, to_idx) = self.add_suffix(suffixes, lo[k], hi[k], to_idx);
+            k -= 1;
+        }
+        self.states[branch as usize] =
+            self.states[branch as usize].add_transition(Some((lo[0], hi[0])), to_idx);
+
+        (self, branch)
+    }
+
+    // The state that reads lo..=hi and goes to `to_idx`, from `suffixes` if it's there.
+    const fn add_suffix(
+        mut self,
+        suffixes: &mut Suffixes,
+        lo: u8,
+        hi: u8,
+        to_idx: NFASize,
+    ) -> (Self, NFASize) {
+        let mut idx = 0;
+        while idx < suffixes.count {
+            let (entry_lo, entry_hi, entry_to, state_idx) = suffixes.entries[idx];
+            if entry_lo == lo && entry_hi == hi && entry_to == to_idx {
+                return (self, state_idx);
+            }
+            idx += 1;
+        }
+
+        let state_idx;
+        (self, state_idx) = self.add_state();
+        self.states[state_idx as usize] =
+            self.states[state_idx as usize].add_transition(Some((lo, hi)), to_idx);
+        if suffixes.count < MAX_SUFFIXES {
+            suffixes.entries[suffixes.count] = (lo, hi, to_idx, state_idx);
+            suffixes.count += 1;
+        }
+        (self, state_idx)
+    }
+
+    const fn add_state(mut self) -> (Self, NFASize) {
+        if self.state_count as usize >= N || self.state_count == NFASize::MAX {
+            panic!("too many NFA states");
+        }
+        let idx = self.state_count;
+        self.state_count += 1;
+        (self, idx)
+    }
+}
+
+/// The first of the pieces lo..=hi splits into, in ascending order: the largest range starting
+/// at `lo` (or just past the surrogates) whose encodings are all the same length and are one
+/// plain sequence of byte ranges. None when the rest of the range is empty.
+pub(super) const fn first_piece(mut lo: u32, hi: u32) -> Option<(u32, u32)> {
+    // Surrogates have no encoding; cut them out.
+    if SURROGATES.0 <= lo && lo <= SURROGATES.1 {
+        lo = SURROGATES.1 + 1;
+    }
+    if lo > hi {
+        return None;
+    }
+    let mut end = hi;
+    if lo < SURROGATES.0 && SURROGATES.0 <= end {
+        end = SURROGATES.0 - 1;
+    }
+
+    // Every piece must encode to the same number of bytes.
+    let mut length = 0;
+    while length < MAX_BY_LENGTH.len() {
+        let max = MAX_BY_LENGTH[length];
+        if lo <= max && max < end {
+            end = max;
+            break;
+        }
+        length += 1;
+    }
+
+    // Every piece must be "a prefix, then ranges, then full continuation ranges": if lo and
+    // end differ above the last k continuation bytes, those k bytes of lo must be all \x80
+    // and those of end all \xbf. Each cut can call for another, so start over after one.
+    let (_, len) = encode(lo);
+    let mut k = 1;
+    while k < len {
+        let mask = (1u32 << (6 * k)) - 1;
+        if lo & !mask != end & !mask {
+            if lo & mask != 0 {
+                end = lo | mask;
+                k = 1;
+                continue;
+            }
+            if end & mask != mask {
+                end = (end & !mask) - 1;
+                k = 1;
+                continue;
+            }
+        }
+        k += 1;
+    }
+
+    Some((lo, end))
+}
+
+/// The ASCII letters in lo..=hi, in the other case: the upper case of the lower case letters,
+/// then the lower case of the upper case ones. Either range may be empty (lo > hi).
+pub(super) const fn other_cases(lo: u32, hi: u32) -> [(u32, u32); 2] {
+    const CASE_DISTANCE: u32 = (b'a' - b'A') as u32;
+    let mut other = [(1, 0); 2];
+    let (lower_lo, lower_hi) = intersect(lo, hi, b'a' as u32, b'z' as u32);
+    if lower_lo <= lower_hi {
+        other[0] = (lower_lo - CASE_DISTANCE, lower_hi - CASE_DISTANCE);
+    }
+    let (upper_lo, upper_hi) = intersect(lo, hi, b'A' as u32, b'Z' as u32);
+    if upper_lo <= upper_hi {
+        other[1] = (upper_lo + CASE_DISTANCE, upper_hi + CASE_DISTANCE);
+    }
+    other
+}
+
+// The overlap of two inclusive ranges; lo > hi if there is none.
+const fn intersect(lo: u32, hi: u32, other_lo: u32, other_hi: u32) -> (u32, u32) {
+    let lo = if lo > other_lo { lo } else { other_lo };
+    let hi = if hi < other_hi { hi } else { other_hi };
+    (lo, hi)
+}
@@ -0,0 +1,389 @@
+use crate::io::Read;
+use crate::regex::{Anchors, DFASize, Dfa, NFASize, Nfa};
+
+const MAX_RULES: usize = 64;
+const MAX_CONDITIONS: usize = 32;
+const MAX_CONDITION_DEPTH: usize = 16;
+const NO_RULE: u8 = u8::MAX;
+
+/// The start condition every scanner begins in. Like lex's `INITIAL`, it is inclusive.
+pub(crate) const INITIAL: &[u8] = b"INITIAL";
+
+/// Reported for a byte that no active rule matches. lex's default rule would `ECHO` it; we hand
+/// it back to the caller instead.
+pub(crate) const UNMATCHED: u16 = u16::MAX;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ConditionKind {
+    /// `%s`: untagged rules are active in this condition, too.
+    Inclusive,
+    /// `%x`: only rules tagged with this condition are active.
+    Exclusive,
+}
+
+#[derive(Clone, Copy)]
+struct Condition {
+    name: &'static [u8],
+    kind: ConditionKind,
+    dfa_start_idx: DFASize,
+}
+
+/// A change of start condition, named by `C`: condition names in an `Action`, condition indices
+/// once the rule has been added.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Switch<C> {
+    Stay,
+    Begin(C),
+    Push(C),
+    Pop,
+}
+
+/// What to do once a rule matches: optionally return a token, and optionally change start
+/// condition. Conditions are named here and resolved when the rule is added, so they must be
+/// declared first -- just like `%x` lines have to come before the rules section in lex.
+#[derive(Clone, Copy)]
+pub(crate) struct Action {
+    token: Option<u16>,
+    switch: Switch<&'static [u8]>,
+}
+
+impl Action {
+    /// Discard the lexeme and keep scanning (whitespace, comments).
+    pub(crate) const SKIP: Self = Self {
+        token: None,
+        switch: Switch::Stay,
+    };
+
+    pub(crate) const fn token(token: u16) -> Self {
+        Self {
+            token: Some(token),
+            switch: Switch::Stay,
+        }
+    }
+
+    /// `BEGIN(condition)`
+    pub(crate) const fn begin(mut self, condition: &'static [u8]) -> Self {
+        self.switch = Switch::Begin(condition);
+        self
+    }
+
+    /// `yy_push_state(condition)`: like `begin`, but remembers the current condition so a later
+    /// `pop` can return to it. This is what nested comments need.
+    pub(crate) const fn push(mut self, condition: &'static [u8]) -> Self {
+        self.switch = Switch::Push(condition);
+        self
+    }
+
+    /// `yy_pop_state()`
+    pub(crate) const fn pop(mut self) -> Self {
+        self.switch = Switch::Pop;
+        self
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Rule {
+    /// One bit per condition the rule was tagged with; 0 for an untagged rule.
+    conditions: u32,
+    accept_idx: NFASize,
+    start_idx: NFASize,
+    token: Option<u16>,
+    switch: Switch<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Lexeme {
+    pub(crate) token: u16,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+/// A lex-style scanner: a list of (pattern, action) rules compiled into a single DFA.
+///
+/// Rules may be prefixed with start conditions, as in lex: `<STRING>x` is only active while the
+/// scanner is in the `STRING` condition, `<A,B>x` in either, and `<*>x` in every condition.
+/// Untagged rules are active in `INITIAL` and in every inclusive condition.
+///
+/// All rules live in one NFA. For each condition we add a branch state with ε-transitions to the
+/// start of every rule active in that condition, then determinize from each branch state into the
+/// same DFA. Each condition ends up with its own DFA start state, and the states reachable from
+/// more than one condition are only built once.
+///
+///     let scanner = Scanner::<256>::new()
+///         .exclusive(b"STRING")
+///         .rule(b"\"", Action::token(QUOTE).begin(b"STRING"))
+///         .rule(b"<STRING>\"", Action::token(QUOTE).begin(INITIAL))
+///         .rule(b"<STRING>a*", Action::token(TEXT))
+///         .build();
+///
+/// When more than one rule matches, the longest match wins; among equally long matches, the rule
+/// added first wins.
+pub(crate) struct Scanner<const N: usize> {
+    nfa: Nfa<N>,
+    dfa: Dfa<N>,
+    rules: [Rule; MAX_RULES],
+    rule_count: usize,
+    conditions: [Condition; MAX_CONDITIONS],
+    condition_count: usize,
+    /// For each DFA state, the index of the rule it accepts, or `NO_RULE`.
+    accepts: [u8; N],
+    current: u8,
+    stack: [u8; MAX_CONDITION_DEPTH],
+    depth: usize,
+    offset: usize,
+}
+
+impl<const N: usize> Scanner<N> {
+    pub(crate) fn new() -> Self {
+        let mut conditions = [Condition {
+            name: b"",
+            kind: ConditionKind::Inclusive,
+            dfa_start_idx: Dfa::<N>::DEAD,
+        }; MAX_CONDITIONS];
+        conditions[0].name = INITIAL;
+
+        Self {
+            nfa: Nfa::new(),
+            dfa: Dfa::new(),
+            rules: [Rule {
+                conditions: 0,
+                accept_idx: 0,
+                start_idx: 0,
+                token: None,
+                switch: Switch::Stay,
+            }; MAX_RULES],
+            rule_count: 0,
+            conditions,
+            condition_count: 1,
+            accepts: [NO_RULE; N],
+            current: 0,
+            stack: [0; MAX_CONDITION_DEPTH],
+            depth: 0,
+            offset: 0,
+        }
+    }
+
+    /// Declare an inclusive start condition (`%s NAME`).
+    pub(crate) fn inclusive(self, name: &'static [u8]) -> Self {
+        self.declare(name, ConditionKind::Inclusive)
+    }
+
+    /// Declare an exclusive start condition (`%x NAME`).
+    pub(crate) fn exclusive(self, name: &'static [u8]) -> Self {
+        self.declare(name, ConditionKind::Exclusive)
+    }
+
+    fn declare(mut self, name: &'static [u8], kind: ConditionKind) -> Self {
+        if self.find_condition(name).is_some() {
+            panic!("start condition declared twice");
+        }
+        if self.condition_count == MAX_CONDITIONS {
+            panic!("too many start conditions");
+        }
+
+        self.conditions[self.condition_count] = Condition {
+            name,
+            kind,
+            dfa_start_idx: Dfa::<N>::DEAD,
+        };
+        self.condition_count += 1;
+        self
+    }
+
+    fn find_condition(&self, name: &[u8]) -> Option<u8> {
+        self.conditions[0..self.condition_count]
+            .iter()
+            .position(|condition| condition.name == name)
+            .map(|idx| idx as u8)
+    }
+
+    fn condition(&self, name: &[u8]) -> u8 {
+        match self.find_condition(name) {
+            Some(condition) => condition,
+            None => panic!("unknown start condition"),
+        }
+    }
+
+    pub(crate) fn rule(mut self, pattern: &'static [u8], action: Action) -> Self {
+        if self.rule_count == MAX_RULES {
+            panic!("too many scanner rules");
+        }
+
+        let (conditions, pattern) = self.split_conditions(pattern);
+        let switch = match action.switch {
+            Switch::Stay => Switch::Stay,
+            Switch::Begin(name) => Switch::Begin(self.condition(name)),
+            Switch::Push(name) => Switch::Push(self.condition(name)),
+            Switch::Pop => Switch::Pop,
+        };
+
+        self.nfa = self.nfa.add_regex_bytes(pattern);
+        if self.nfa.anchors() != Anchors::NONE {
+            panic!("scanner rules can't use \"^\" or \"$\"");
+        }
+        self.rules[self.rule_count] = Rule {
+            conditions,
+            accept_idx: self.nfa.accept_idx(),
+            start_idx: self.nfa.start_idx(),
+            token: action.token,
+            switch,
+        };
+        self.rule_count += 1;
+        self
+    }
+
+    // Split a leading "<A,B>" (or "<*>") off of a rule. A leading "<" that isn't followed by a
+    // list of condition names and a ">" is left alone as a literal.
+    fn split_conditions(&self, pattern: &'static [u8]) -> (u32, &'static [u8]) {
+        if pattern.first() != Some(&b'<') {
+            return (0, pattern);
+        }
+
+        let Some(close) = pattern.iter().position(|chara| *chara == b'>') else {
+            return (0, pattern);
+        };
+
+        let names = &pattern[1..close];
+        if names.is_empty()
+            || !names
+                .iter()
+                .all(|chara| matches!(chara, b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b',' | b'*'))
+        {
+            return (0, pattern);
+        }
+
+        let mut conditions = 0u32;
+        for name in names.split(|chara| *chara == b',') {
+            conditions |= if name == b"*" {
+                u32::MAX
+            } else {
+                1 << self.condition(name)
+            };
+        }
+
+        (conditions, &pattern[close + 1..])
+    }
+
+    fn is_active(&self, rule: &Rule, condition: u8) -> bool {
+        if rule.conditions == 0 {
+            self.conditions[condition as usize].kind == ConditionKind::Inclusive
+        } else {
+            rule.conditions & (1 << condition) != 0
+        }
+    }
+
+    /// Build the shared DFA. Call this after the last `rule`.
+    pub(crate) fn build(mut self) -> Self {
+        for condition in 0..self.condition_count {
+            let mut targets = [0 as NFASize; MAX_RULES];
+            let mut target_count = 0;
+            for rule in &self.rules[0..self.rule_count] {
+                if self.is_active(rule, condition as u8) {
+                    targets[target_count] = rule.start_idx;
+                    target_count += 1;
+                }
+            }
+
+            let branch_idx = self.nfa.add_branch(&targets[0..target_count]);
+            self.conditions[condition].dfa_start_idx = self.dfa.add_start(&self.nfa, branch_idx);
+        }
+
+        // Rule fragments don't share NFA states, so a DFA state contains the accepting state of a
+        // rule only if that rule is active in every condition that can reach it.
+        for state in 0..self.dfa.state_count() {
+            let nfa_states = self.dfa.nfa_states(state);
+            self.accepts[state as usize] = self.rules[0..self.rule_count]
+                .iter()
+                .position(|rule| nfa_states.contains(rule.accept_idx))
+                .map(|idx| idx as u8)
+                .unwrap_or(NO_RULE);
+        }
+
+        self
+    }
+
+    /// The name of the current start condition.
+    pub(crate) fn current(&self) -> &'static [u8] {
+        self.conditions[self.current as usize].name
+    }
+
+    /// Scan the next token from `input`, running the actions of any skipped lexemes along the
+    /// way. Returns `None` at the end of input.
+    pub(crate) fn next_token<R: Read>(&mut self, input: &mut R) -> Option<Lexeme> {
+        loop {
+            let start = self.offset;
+            let mut state = self.conditions[self.current as usize].dfa_start_idx;
+            let mut consumed = 0;
+            let mut last_accept: Option<(u8, usize)> = None;
+
+            loop {
+                let Some(chara) = input.getc() else {
+                    // Reading past the end moves the input along, too.
+                    input.ungetc();
+                    break;
+                };
+                consumed += 1;
+                state = self.dfa.step(state, chara);
+                if state == Dfa::<N>::DEAD {
+                    break;
+                }
+
+                let rule = self.accepts[state as usize];
+                if rule != NO_RULE {
+                    last_accept = Some((rule, consumed));
+                }
+            }
+
+            let Some((rule, len)) = last_accept else {
+                // Nothing matched. Hand back a single byte so scanning can make progress.
+                if consumed == 0 {
+                    return None;
+                }
+                for _ in 1..consumed {
+                    input.ungetc();
+                }
+                self.offset += 1;
+                return Some(Lexeme {
+                    token: UNMATCHED,
+                    start,
+                    end: self.offset,
+                });
+            };
+
+            // Maximal munch: give back everything we read past the end of the longest match.
+            for _ in len..consumed {
+                input.ungetc();
+            }
+            self.offset += len;
+
+            let rule = self.rules[rule as usize];
+            match rule.switch {
+                Switch::Stay => {}
+                Switch::Begin(condition) => self.current = condition,
+                Switch::Push(condition) => {
+                    if self.depth == MAX_CONDITION_DEPTH {
+                        panic!("start condition stack overflow");
+                    }
+                    self.stack[self.depth] = self.current;
+                    self.depth += 1;
+                    self.current = condition;
+                }
+                Switch::Pop => {
+                    if self.depth == 0 {
+                        panic!("start condition stack underflow");
+                    }
+                    self.depth -= 1;
+                    self.current = self.stack[self.depth];
+                }
+            }
+
+            if let Some(token) = rule.token {
+                return Some(Lexeme {
+                    token,
+                    start,
+                    end: self.offset,
+                });
+            }
+        }
+    }
+}
@@ -16,6 +16,34 @@ pub(crate) unsafe fn syscall3(syscall_number: u64, arg0: usize, arg1: usize, arg
     arg0
 }
 
+/// Like `syscall3`, but tells failure apart from success: the kernel sets the carry flag when
+/// a call fails, and then the result is an errno.
+#[cfg(target_arch = "aarch64")]
+pub(crate) unsafe fn syscall3_checked(
+    syscall_number: u64,
+    arg0: usize,
+    arg1: usize,
+    arg2: usize,
+) -> Result<usize, usize> {
+    let mut arg0 = arg0;
+    let failed: u64;
+    core::arch::asm!(
+        "svc 0",
+        "cset {failed}, cs",
+        failed = out(reg) failed,
+        in("x16") syscall_number,
+        inout("x0") arg0,
+        in("x1") arg1,
+        in("x2") arg2,
+        options(nostack)
+    );
+    if failed != 0 {
+        Err(arg0)
+    } else {
+        Ok(arg0)
+    }
+}
+
 #[cfg(target_arch = "aarch64")]
 pub(crate) fn exit(code: i32) -> ! {
     flush();
@@ -30,6 +58,73 @@ pub(crate) fn exit(code: i32) -> ! {
     }
 }
 
+/// Fill `buf` (at most 256 bytes) with random bytes from the kernel.
+pub(crate) fn getentropy(buf: &mut [u8]) -> bool {
+    if buf.len() > 256 {
+        return false;
+    }
+    unsafe { syscall3(500, buf.as_mut_ptr() as usize, buf.len(), 0) == 0 }
+}
+
+// The longest path `open` takes.
+const MAX_PATH_LEN: usize = 1024;
+
+/// Open the file at `path` for reading, and return its file descriptor.
+pub(crate) fn open(path: &[u8]) -> Option<usize> {
+    const O_RDONLY: usize = 0;
+
+    // The kernel wants a NUL-terminated path.
+    let mut c_path = [0u8; MAX_PATH_LEN + 1];
+    if path.len() > MAX_PATH_LEN || path.contains(&0) {
+        return None;
+    }
+    c_path[0..path.len()].copy_from_slice(path);
+    unsafe { syscall3_checked(5, c_path.as_ptr() as usize, O_RDONLY, 0).ok() }
+}
+
+pub(crate) fn close(fd: usize) {
+    unsafe { syscall3(6, fd, 0, 0) };
+}
+
+/// The command line, as the C runtime hands it to `main`.
+#[derive(Clone, Copy)]
+pub(crate) struct Args {
+    argc: usize,
+    argv: *const *const u8,
+}
+
+impl Args {
+    /// # Safety
+    /// `argv` must point at `argc` NUL-terminated strings that live as long as the process, as
+    /// the ones passed to `main` do.
+    pub(crate) unsafe fn new(argc: i32, argv: *const *const u8) -> Self {
+        Self {
+            argc: argc.max(0) as usize,
+            argv,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.argc
+    }
+
+    /// The `idx`th argument (the program name is the 0th), without its NUL.
+    pub(crate) fn get(&self, idx: usize) -> Option<&'static [u8]> {
+        if idx >= self.argc {
+            return None;
+        }
+
+        unsafe {
+            let arg = *self.argv.add(idx);
+            let mut len = 0;
+            while *arg.add(len) != 0 {
+                len += 1;
+            }
+            Some(core::slice::from_raw_parts(arg, len))
+        }
+    }
+}
+
 #[cfg(not(test))]
 #[lang = "eh_personality"]
 fn eh_personality() {}
@@ -37,13 +132,21 @@ fn eh_personality() {}
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(panic_info: &core::panic::PanicInfo) -> ! {
-    flush();
-    if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
-        eputs("panic: \"");
-        eputs(s);
-        eputs("\"\n");
+    // The message may have arguments to format, and formatting needs a `core::fmt::Write`.
+    struct Stderr;
+
+    impl core::fmt::Write for Stderr {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            eputs(s);
+            Ok(())
+        }
     }
 
+    flush();
+    eputs("panic: \"");
+    let _ = core::fmt::write(&mut Stderr, format_args!("{}", panic_info.message()));
+    eputs("\"\n");
+
     if let Some(loc) = panic_info.location() {
         eputs("panic: in \"");
         eputs(loc.file());
@@ -52,5 +155,6 @@ fn panic(panic_info: &core::panic::PanicInfo) -> ! {
         eputs("\n");
     }
 
-    exit(1);
+    // Not 1: the subcommands use that for "no".
+    exit(2);
 }